@@ -1,6 +1,6 @@
 use std::io::BufRead;
 
-use rualign::Aligner;
+use rualign::{Aligner, AlignerConfig, TagPropagation};
 
 use vaporetto::Sentence;
 
@@ -11,20 +11,26 @@ fn main() {
         sentences.push(Sentence::from_tokenized(&line).unwrap());
     }
     eprintln!("Initializing...");
-    let mut aligner = Aligner::new(&sentences, 1);
+    let mut aligner = Aligner::new(&sentences, 1, AlignerConfig::default()).unwrap();
     eprintln!("Training...");
     for i in 0..20 {
-        let log_diff = aligner.update();
-        eprintln!("#{i} log_diff: {log_diff}");
+        let stats = aligner.update().unwrap();
+        let log_diff = stats.log_squared_error();
+        eprintln!(
+            "#{i} log_likelihood: {} log_diff: {log_diff}",
+            stats.log_likelihood(),
+        );
         if log_diff < -20.0 {
             break;
         }
     }
     eprintln!("Finalizing...");
-    let phoneme_map = aligner.finalize();
+    let phoneme_map = aligner.finalize().unwrap();
     let mut buf = String::new();
     for mut sentence in sentences {
-        phoneme_map.make_alignment(&mut sentence, 1);
+        phoneme_map
+            .make_alignment(&mut sentence, 1, 1, TagPropagation::Copy)
+            .unwrap();
         sentence.write_tokenized_text(&mut buf);
         println!("{}", buf);
     }
@@ -0,0 +1,303 @@
+use core::ops::Range;
+
+use crate::errors::Result;
+use crate::normalize::NormalizedText;
+
+/// Normalization of fragment pair scores in the M-step.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Normalization {
+    /// Normalizes over all pairs, giving the joint probability P(surface, reading).
+    Joint,
+
+    /// Normalizes per surface fragment, giving the conditional probability P(reading | surface).
+    Surface,
+
+    /// Normalizes per reading fragment, giving the conditional probability P(surface | reading).
+    Reading,
+}
+
+/// Prior distribution of fragment pairs used in the M-step.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Prior {
+    /// Maximum likelihood estimation without a prior.
+    None,
+
+    /// Adds the given pseudo-count to the expected count of each pair, which corresponds to the
+    /// MAP estimation with a symmetric Dirichlet prior.
+    Additive(f64),
+
+    /// Variational Bayes estimation with a symmetric Dirichlet prior of the given concentration
+    /// parameter. A value smaller than 1 favors a small number of frequent pairs.
+    VariationalBayes(f64),
+}
+
+/// Hyperparameters of [`Aligner`](crate::Aligner).
+///
+/// # Examples
+///
+/// ```
+/// use rualign::AlignerConfig;
+///
+/// let config = AlignerConfig::new()
+///     .max_surface_span(2)
+///     .max_reading_span(4)
+///     .deletion_penalty(2.0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct AlignerConfig {
+    pub(crate) max_surface_span: usize,
+    pub(crate) max_reading_span: usize,
+    pub(crate) allow_empty_reading: bool,
+    pub(crate) deletion_penalty: f64,
+    pub(crate) merge: bool,
+    pub(crate) two_phase_training: bool,
+    pub(crate) pruning_threshold: f64,
+    pub(crate) prior: Prior,
+    pub(crate) normalization: Normalization,
+    pub(crate) kana_identity: bool,
+    pub(crate) normalize_readings: bool,
+    pub(crate) normalize_surfaces: bool,
+    #[cfg(feature = "parallel")]
+    pub(crate) num_threads: usize,
+}
+
+impl AlignerConfig {
+    /// Creates a new configuration with the default values.
+    ///
+    /// By default, spans are unbounded, empty readings are allowed, the deletion penalty is 1.0,
+    /// and mergeable fragments are merged.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            max_surface_span: usize::MAX,
+            max_reading_span: usize::MAX,
+            allow_empty_reading: true,
+            deletion_penalty: 1.0,
+            merge: true,
+            two_phase_training: false,
+            pruning_threshold: 0.0,
+            prior: Prior::None,
+            normalization: Normalization::Joint,
+            kana_identity: false,
+            normalize_readings: false,
+            normalize_surfaces: false,
+            #[cfg(feature = "parallel")]
+            num_threads: 0,
+        }
+    }
+
+    /// Sets the maximum number of characters in a surface fragment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `span` is 0.
+    #[must_use]
+    pub const fn max_surface_span(mut self, span: usize) -> Self {
+        assert!(span != 0, "max_surface_span must be positive");
+        self.max_surface_span = span;
+        self
+    }
+
+    /// Sets the maximum number of characters in a reading fragment.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `span` is 0.
+    #[must_use]
+    pub const fn max_reading_span(mut self, span: usize) -> Self {
+        assert!(span != 0, "max_reading_span must be positive");
+        self.max_reading_span = span;
+        self
+    }
+
+    /// Sets whether a surface fragment can be aligned to an empty reading.
+    #[must_use]
+    pub const fn allow_empty_reading(mut self, allow: bool) -> Self {
+        self.allow_empty_reading = allow;
+        self
+    }
+
+    /// Sets the length counted for an empty reading fragment in the city block distance.
+    ///
+    /// The score of an edge is multiplied by its distance, so a larger value suppresses
+    /// alignments to empty readings.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `penalty` is negative or not finite.
+    #[must_use]
+    pub fn deletion_penalty(mut self, penalty: f64) -> Self {
+        assert!(
+            penalty.is_finite() && penalty >= 0.0,
+            "deletion_penalty must be a non-negative finite number",
+        );
+        self.deletion_penalty = penalty;
+        self
+    }
+
+    /// Sets whether fragments that always appear together are merged by
+    /// [`Aligner::finalize()`](crate::Aligner::finalize).
+    #[must_use]
+    pub const fn merge(mut self, merge: bool) -> Self {
+        self.merge = merge;
+        self
+    }
+
+    /// Sets whether to use the two-phase training of the original algorithm.
+    ///
+    /// If enabled, pairs of an empty reading are initialized with zero probability, so the EM
+    /// training of [`Aligner::update()`](crate::Aligner::update) excludes them. They are learned
+    /// afterward by the n-best Viterbi training of
+    /// [`Aligner::update_n_best()`](crate::Aligner::update_n_best). Otherwise, the EM training
+    /// learns them from the beginning, suppressed by the deletion penalty.
+    #[must_use]
+    pub const fn two_phase_training(mut self, enable: bool) -> Self {
+        self.two_phase_training = enable;
+        self
+    }
+
+    /// Sets the probability below which fragment pairs are pruned after each training iteration.
+    ///
+    /// Pruned pairs are removed from the model and treated as impossible in later iterations.
+    /// Pairs of an empty reading are not pruned if the two-phase training is enabled. The default
+    /// is 0, which disables pruning.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threshold` is not in the range `[0, 1]`.
+    #[must_use]
+    pub fn pruning_threshold(mut self, threshold: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&threshold),
+            "pruning_threshold must be in the range [0, 1]",
+        );
+        self.pruning_threshold = threshold;
+        self
+    }
+
+    /// Sets the prior distribution of fragment pairs. The default is [`Prior::None`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameter of the prior is not a positive finite number.
+    #[must_use]
+    pub fn prior(mut self, prior: Prior) -> Self {
+        if let Prior::Additive(alpha) | Prior::VariationalBayes(alpha) = prior {
+            assert!(
+                alpha.is_finite() && alpha > 0.0,
+                "the parameter of the prior must be a positive finite number",
+            );
+        }
+        self.prior = prior;
+        self
+    }
+
+    /// Sets how scores are normalized. The default is [`Normalization::Joint`].
+    #[must_use]
+    pub const fn normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    /// Sets whether kana in surfaces are tied to the same kana in readings.
+    ///
+    /// If enabled, each hiragana or katakana in surfaces forms a fragment by itself and is
+    /// aligned to the same kana, regardless of hiragana/katakana, in the training data. Words
+    /// that cannot be aligned under this restriction are trained without it.
+    ///
+    /// The restriction only applies to training. Pairs searched after training, e.g., by
+    /// [`PhonemeMap::align_n_best()`](crate::PhonemeMap::align_n_best) or unseen pairs given to
+    /// [`PhonemeMap::align()`](crate::PhonemeMap::align), are aligned using the trained scores
+    /// without it.
+    #[must_use]
+    pub const fn kana_identity(mut self, enable: bool) -> Self {
+        self.kana_identity = enable;
+        self
+    }
+
+    /// Sets whether readings are normalized before training.
+    ///
+    /// If enabled, katakana are converted into hiragana, half-width katakana into full-width
+    /// ones, and full-width ASCII characters into ASCII, so readings written in different forms
+    /// are trained as the same symbols. Alignments are still reported in the original characters,
+    /// but fragment scores and readings predicted by [`Predictor`](crate::Predictor) are in the
+    /// normalized form.
+    #[must_use]
+    pub const fn normalize_readings(mut self, enable: bool) -> Self {
+        self.normalize_readings = enable;
+        self
+    }
+
+    /// Sets whether the widths of characters in surfaces are normalized before training.
+    ///
+    /// If enabled, full-width ASCII characters are converted into ASCII, and half-width katakana
+    /// into full-width ones. Unlike readings, katakana are kept as is. Alignments are still
+    /// reported in the original characters.
+    #[must_use]
+    pub const fn normalize_surfaces(mut self, enable: bool) -> Self {
+        self.normalize_surfaces = enable;
+        self
+    }
+
+    /// Sets the number of threads used in the E-step.
+    ///
+    /// If 0 is given, the number of available CPUs is used. The default is 0.
+    #[cfg(feature = "parallel")]
+    #[must_use]
+    pub const fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads;
+        self
+    }
+
+    /// Returns the number of threads used in the E-step.
+    #[cfg(feature = "parallel")]
+    pub(crate) fn get_num_threads(&self) -> usize {
+        if self.num_threads == 0 {
+            std::thread::available_parallelism().map_or(1, core::num::NonZeroUsize::get)
+        } else {
+            self.num_threads
+        }
+    }
+
+    /// Converts the given surface into the form used in training.
+    pub(crate) fn normalize_surface<'a>(&self, surface: &'a str) -> NormalizedText<'a> {
+        NormalizedText::new(surface, self.normalize_surfaces, false)
+    }
+
+    /// Converts the given reading into the form used in training.
+    pub(crate) fn normalize_reading<'a>(&self, reading: &'a str) -> NormalizedText<'a> {
+        NormalizedText::new(reading, self.normalize_readings, self.normalize_readings)
+    }
+
+    /// Returns the city block distance of the edge from `(i, j)` to `(p, q)`.
+    pub(crate) fn distance(&self, i: usize, j: usize, p: usize, q: usize) -> Result<f64> {
+        let surface_span = f64::from(u32::try_from(p - i)?);
+        let reading_span = if q == j {
+            self.deletion_penalty
+        } else {
+            f64::from(u32::try_from(q - j)?)
+        };
+        Ok(surface_span + reading_span)
+    }
+
+    /// Returns the end positions of surface fragments starting at `start`.
+    pub(crate) fn surface_ends(&self, start: usize, len: usize) -> Range<usize> {
+        start + 1..start.saturating_add(self.max_surface_span).min(len) + 1
+    }
+
+    /// Returns the end positions of reading fragments starting at `start`.
+    pub(crate) fn reading_ends(&self, start: usize, len: usize) -> Range<usize> {
+        let first = if self.allow_empty_reading {
+            start
+        } else {
+            start + 1
+        };
+        first..start.saturating_add(self.max_reading_span).min(len) + 1
+    }
+}
+
+impl Default for AlignerConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
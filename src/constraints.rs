@@ -0,0 +1,153 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::config::AlignerConfig;
+use crate::errors::{Error, Result};
+
+/// Anchors of each pair of a surface and a reading.
+type AnchorMap = HashMap<Vec<char>, HashMap<Vec<char>, Vec<(usize, usize)>>>;
+
+/// Constraints on alignments of the training data.
+///
+/// # Examples
+///
+/// ```
+/// use rualign::{Aligner, AlignerConfig, Constraints};
+///
+/// let mut constraints = Constraints::new();
+/// constraints.allow("日", "に");
+/// constraints.allow("日", "ひ");
+/// constraints.forbid("す", "");
+/// constraints.anchor("する", "する", 1, 1);
+///
+/// let pairs = [("日本", "にほん"), ("する", "する")];
+/// let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new())?;
+/// aligner.apply_constraints(&constraints)?;
+/// # Ok::<(), rualign::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Constraints {
+    allowed: HashMap<Vec<char>, HashSet<Vec<char>>>,
+    forbidden: HashMap<Vec<char>, HashSet<Vec<char>>>,
+    anchors: AnchorMap,
+}
+
+impl Constraints {
+    /// Creates empty constraints.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an allowed reading of the given surface fragment.
+    ///
+    /// Once a surface fragment is given allowed readings, pairs of the fragment and other
+    /// readings are forbidden.
+    pub fn allow(&mut self, surface: &str, reading: &str) {
+        self.allowed
+            .entry(surface.chars().collect())
+            .or_default()
+            .insert(reading.chars().collect());
+    }
+
+    /// Forbids the given pair of a surface fragment and a reading fragment.
+    pub fn forbid(&mut self, surface: &str, reading: &str) {
+        self.forbidden
+            .entry(surface.chars().collect())
+            .or_default()
+            .insert(reading.chars().collect());
+    }
+
+    /// Forces a fragment boundary after `surface_pos` characters of the surface and `reading_pos`
+    /// characters of the reading in the given word.
+    ///
+    /// Positions past the end of the word are rejected by
+    /// [`Aligner::apply_constraints()`](crate::Aligner::apply_constraints). Anchors only apply to
+    /// the training data, so they are not used by
+    /// [`PhonemeMap::align_n_best()`](crate::PhonemeMap::align_n_best) or for pairs not in the
+    /// training data.
+    pub fn anchor(&mut self, surface: &str, reading: &str, surface_pos: usize, reading_pos: usize) {
+        self.anchors
+            .entry(surface.chars().collect())
+            .or_default()
+            .entry(reading.chars().collect())
+            .or_default()
+            .push((surface_pos, reading_pos));
+    }
+
+    /// Returns the constraints converted into the form used in training.
+    ///
+    /// An error is returned if an anchor is placed outside its word.
+    pub(crate) fn normalize(&self, config: &AlignerConfig) -> Result<Self> {
+        let normalize_pairs = |pairs: &HashMap<Vec<char>, HashSet<Vec<char>>>| {
+            let mut normalized: HashMap<_, HashSet<_>> = HashMap::new();
+            for (surface, readings) in pairs {
+                let surface: String = surface.iter().collect();
+                let entry = normalized
+                    .entry(config.normalize_surface(&surface).into_chars())
+                    .or_default();
+                for reading in readings {
+                    let reading: String = reading.iter().collect();
+                    entry.insert(config.normalize_reading(&reading).into_chars());
+                }
+            }
+            normalized
+        };
+        let mut anchors = AnchorMap::new();
+        for (surface, readings) in &self.anchors {
+            let surface: String = surface.iter().collect();
+            let surface = config.normalize_surface(&surface);
+            for (reading, positions) in readings {
+                let reading: String = reading.iter().collect();
+                let reading = config.normalize_reading(&reading);
+                let entry = anchors
+                    .entry(surface.chars().to_vec())
+                    .or_default()
+                    .entry(reading.chars().to_vec())
+                    .or_default();
+                for &(surface_pos, reading_pos) in positions {
+                    if surface_pos > surface.text().chars().count()
+                        || reading_pos > reading.text().chars().count()
+                    {
+                        return Err(Error::invalid_model(format!(
+                            "anchor ({surface_pos}, {reading_pos}) is out of range of {}/{}",
+                            surface.text(),
+                            reading.text(),
+                        )));
+                    }
+                    entry.push((
+                        surface.normalized_pos(surface_pos),
+                        reading.normalized_pos(reading_pos),
+                    ));
+                }
+            }
+        }
+        Ok(Self {
+            allowed: normalize_pairs(&self.allowed),
+            forbidden: normalize_pairs(&self.forbidden),
+            anchors,
+        })
+    }
+
+    /// Returns whether the given pair of fragments is forbidden.
+    pub(crate) fn is_forbidden(&self, surface: &[char], reading: &[char]) -> bool {
+        if let Some(readings) = self.allowed.get(surface) {
+            if !readings.contains(reading) {
+                return true;
+            }
+        }
+        self.forbidden
+            .get(surface)
+            .is_some_and(|readings| readings.contains(reading))
+    }
+
+    /// Returns anchors of the given word.
+    pub(crate) fn anchors(&self, surface: &[char], reading: &[char]) -> &[(usize, usize)] {
+        self.anchors
+            .get(surface)
+            .and_then(|anchors| anchors.get(reading))
+            .map_or(&[], Vec::as_slice)
+    }
+}
@@ -0,0 +1,75 @@
+//! Definition of errors.
+
+use core::fmt;
+
+use alloc::string::String;
+
+/// A specialized Result type for rualign.
+pub type Result<T, E = Error> = core::result::Result<T, E>;
+
+/// The error type for rualign.
+#[derive(Debug)]
+pub enum Error {
+    /// The error variant for [`InvalidModelError`].
+    InvalidModel(InvalidModelError),
+
+    /// The error variant for [`TryFromIntError`](core::num::TryFromIntError).
+    CastError(core::num::TryFromIntError),
+
+    /// The error variant for [`std::io::Error`].
+    #[cfg(feature = "std")]
+    IOError(std::io::Error),
+}
+
+impl Error {
+    pub(crate) fn invalid_model<S>(msg: S) -> Self
+    where
+        S: Into<String>,
+    {
+        Self::InvalidModel(InvalidModelError { msg: msg.into() })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidModel(e) => e.fmt(f),
+            Self::CastError(e) => e.fmt(f),
+
+            #[cfg(feature = "std")]
+            Self::IOError(e) => e.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
+
+/// Error used when the model data is invalid.
+#[derive(Debug)]
+pub struct InvalidModelError {
+    /// Error message.
+    pub(crate) msg: String,
+}
+
+impl fmt::Display for InvalidModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "InvalidModelError: {}", self.msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidModelError {}
+
+impl From<core::num::TryFromIntError> for Error {
+    fn from(error: core::num::TryFromIntError) -> Self {
+        Self::CastError(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Self::IOError(error)
+    }
+}
@@ -0,0 +1,285 @@
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::array_2d::Array2d;
+use crate::config::AlignerConfig;
+use crate::errors::Result;
+
+/// Pair ID of an edge whose fragment pair is not registered.
+pub const NO_PAIR: u32 = u32::MAX;
+
+/// Trie over fragments. Each node corresponds to a fragment, and its index is used as the ID of
+/// the fragment. The root node (ID 0) corresponds to the empty fragment.
+struct FragmentTrie {
+    children: HashMap<(u32, char), u32>,
+
+    // Parent node and the last character of each node.
+    parents: Vec<(u32, char)>,
+}
+
+impl FragmentTrie {
+    fn new() -> Self {
+        Self {
+            children: HashMap::new(),
+            parents: vec![(0, '\0')],
+        }
+    }
+
+    fn child(&self, node: u32, c: char) -> Option<u32> {
+        self.children.get(&(node, c)).copied()
+    }
+
+    fn get_or_insert_child(&mut self, node: u32, c: char) -> Result<u32> {
+        if let Some(child) = self.child(node, c) {
+            return Ok(child);
+        }
+        let child = u32::try_from(self.parents.len())?;
+        self.children.insert((node, c), child);
+        self.parents.push((node, c));
+        Ok(child)
+    }
+
+    fn get_or_insert(&mut self, chars: &[char]) -> Result<u32> {
+        chars
+            .iter()
+            .try_fold(0, |node, &c| self.get_or_insert_child(node, c))
+    }
+
+    fn fragment(&self, mut node: u32) -> Vec<char> {
+        let mut chars = vec![];
+        while node != 0 {
+            let (parent, c) = self.parents[usize::try_from(node).unwrap()];
+            chars.push(c);
+            node = parent;
+        }
+        chars.reverse();
+        chars
+    }
+
+    /// Returns IDs of the fragments `chars[i..p]` at `(i, p)` for all `p - i <= max_span`.
+    /// Unregistered fragments are set to [`NO_PAIR`].
+    fn fragment_ids<F>(chars: &[char], max_span: usize, mut child: F) -> Result<Array2d<u32>>
+    where
+        F: FnMut(u32, char) -> Result<Option<u32>>,
+    {
+        let mut ids = Array2d::new(chars.len() + 1, chars.len() + 1);
+        ids.fill(NO_PAIR);
+        for i in 0..chars.len() + 1 {
+            let mut node = 0;
+            *ids.get_mut(i, i).unwrap() = node;
+            for p in i + 1..i.saturating_add(max_span).min(chars.len()) + 1 {
+                let Some(next) = child(node, chars[p - 1])? else {
+                    break;
+                };
+                node = next;
+                *ids.get_mut(i, p).unwrap() = node;
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// Scores of fragment pairs.
+///
+/// Fragments are interned into integer IDs, and each pair of a surface fragment and a reading
+/// fragment is assigned a dense pair ID that indexes the score array.
+pub struct FragmentTable {
+    surfaces: FragmentTrie,
+    readings: FragmentTrie,
+    pair_ids: HashMap<(u32, u32), u32>,
+    pairs: Vec<(u32, u32)>,
+    scores: Vec<f64>,
+}
+
+impl FragmentTable {
+    pub fn new() -> Self {
+        Self {
+            surfaces: FragmentTrie::new(),
+            readings: FragmentTrie::new(),
+            pair_ids: HashMap::new(),
+            pairs: vec![],
+            scores: vec![],
+        }
+    }
+
+    /// Returns the number of pairs.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn scores(&self) -> &[f64] {
+        &self.scores
+    }
+
+    pub fn scores_mut(&mut self) -> &mut [f64] {
+        &mut self.scores
+    }
+
+    /// Returns IDs of the surface fragment and the reading fragment of the given pair.
+    pub fn pair(&self, pair_id: usize) -> (u32, u32) {
+        self.pairs[pair_id]
+    }
+
+    /// Returns whether the reading fragment of the given pair is empty.
+    pub fn is_deletion(&self, pair_id: usize) -> bool {
+        self.pairs[pair_id].1 == 0
+    }
+
+    fn get_or_insert_pair_id(
+        &mut self,
+        surface_id: u32,
+        reading_id: u32,
+        score: f64,
+    ) -> Result<u32> {
+        if let Some(&pair_id) = self.pair_ids.get(&(surface_id, reading_id)) {
+            return Ok(pair_id);
+        }
+        let pair_id = u32::try_from(self.pairs.len())?;
+        self.pair_ids.insert((surface_id, reading_id), pair_id);
+        self.pairs.push((surface_id, reading_id));
+        self.scores.push(score);
+        Ok(pair_id)
+    }
+
+    /// Removes pairs for which `f` returns `false`, and returns new IDs of pairs indexed by the
+    /// old IDs. Removed pairs are mapped to [`NO_PAIR`]. The order of the remaining pairs is
+    /// preserved.
+    pub fn retain<F>(&mut self, mut f: F) -> Result<Vec<u32>>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut new_ids = Vec::with_capacity(self.pairs.len());
+        let mut num_pairs = 0;
+        for pair_id in 0..self.pairs.len() {
+            if f(pair_id) {
+                self.pairs[num_pairs] = self.pairs[pair_id];
+                self.scores[num_pairs] = self.scores[pair_id];
+                new_ids.push(u32::try_from(num_pairs)?);
+                num_pairs += 1;
+            } else {
+                new_ids.push(NO_PAIR);
+            }
+        }
+        self.pairs.truncate(num_pairs);
+        self.pairs.shrink_to_fit();
+        self.scores.truncate(num_pairs);
+        self.scores.shrink_to_fit();
+        self.pair_ids.clear();
+        for (pair_id, &pair) in self.pairs.iter().enumerate() {
+            self.pair_ids.insert(pair, u32::try_from(pair_id)?);
+        }
+        self.pair_ids.shrink_to_fit();
+        Ok(new_ids)
+    }
+
+    /// Sets the score of the given pair, inserting the pair if it is not registered.
+    pub fn insert(&mut self, surface: &[char], reading: &[char], score: f64) -> Result<()> {
+        let surface_id = self.surfaces.get_or_insert(surface)?;
+        let reading_id = self.readings.get_or_insert(reading)?;
+        let pair_id = self.get_or_insert_pair_id(surface_id, reading_id, score)?;
+        self.scores[usize::try_from(pair_id)?] = score;
+        Ok(())
+    }
+
+    /// Calls `f` for each pair with its fragments and score.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(Vec<char>, Vec<char>, f64),
+    {
+        for (&(surface_id, reading_id), &score) in self.pairs.iter().zip(&self.scores) {
+            f(
+                self.surfaces.fragment(surface_id),
+                self.readings.fragment(reading_id),
+                score,
+            );
+        }
+    }
+
+    /// Returns pair IDs of all edges in the lattice of the given word in the order of
+    /// [`for_each_edge()`]. Unregistered pairs are set to [`NO_PAIR`].
+    pub fn lattice(
+        &self,
+        config: &AlignerConfig,
+        surface: &[char],
+        reading: &[char],
+    ) -> Result<Vec<u32>> {
+        let surface_ids =
+            FragmentTrie::fragment_ids(surface, config.max_surface_span, |node, c| {
+                Ok(self.surfaces.child(node, c))
+            })?;
+        let reading_ids =
+            FragmentTrie::fragment_ids(reading, config.max_reading_span, |node, c| {
+                Ok(self.readings.child(node, c))
+            })?;
+        let mut pair_ids = vec![];
+        for_each_edge(config, surface.len(), reading.len(), |i, j, p, q| {
+            let surface_id = *surface_ids.get(i, p).unwrap();
+            let reading_id = *reading_ids.get(j, q).unwrap();
+            pair_ids.push(if surface_id == NO_PAIR || reading_id == NO_PAIR {
+                NO_PAIR
+            } else {
+                self.pair_ids
+                    .get(&(surface_id, reading_id))
+                    .copied()
+                    .unwrap_or(NO_PAIR)
+            });
+        });
+        Ok(pair_ids)
+    }
+
+    /// Registers missing pairs in the lattice of the given word with `init_score`.
+    pub fn insert_lattice(
+        &mut self,
+        config: &AlignerConfig,
+        surface: &[char],
+        reading: &[char],
+        init_score: f64,
+    ) -> Result<()> {
+        let surface_ids =
+            FragmentTrie::fragment_ids(surface, config.max_surface_span, |node, c| {
+                self.surfaces.get_or_insert_child(node, c).map(Some)
+            })?;
+        let reading_ids =
+            FragmentTrie::fragment_ids(reading, config.max_reading_span, |node, c| {
+                self.readings.get_or_insert_child(node, c).map(Some)
+            })?;
+        let mut edges = vec![];
+        for_each_edge(config, surface.len(), reading.len(), |i, j, p, q| {
+            edges.push((
+                *surface_ids.get(i, p).unwrap(),
+                *reading_ids.get(j, q).unwrap(),
+            ));
+        });
+        for (surface_id, reading_id) in edges {
+            self.get_or_insert_pair_id(surface_id, reading_id, init_score)?;
+        }
+        Ok(())
+    }
+}
+
+/// Calls `f` with `(i, j, p, q)` for each edge from `(i, j)` to `(p, q)` in the lattice of a word
+/// in the topological order.
+///
+/// The DP functions iterate edges in the same order (or in the reverse order) to look up pair IDs
+/// returned by [`FragmentTable::lattice()`] sequentially.
+pub fn for_each_edge<F>(config: &AlignerConfig, surface_len: usize, reading_len: usize, mut f: F)
+where
+    F: FnMut(usize, usize, usize, usize),
+{
+    for i in 0..surface_len {
+        for j in 0..reading_len + 1 {
+            if i == 0 && j != 0 {
+                continue;
+            }
+            for p in config.surface_ends(i, surface_len) {
+                for q in config.reading_ends(j, reading_len) {
+                    if p == surface_len && q != reading_len {
+                        continue;
+                    }
+                    f(i, j, p, q);
+                }
+            }
+        }
+    }
+}
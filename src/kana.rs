@@ -0,0 +1,63 @@
+use crate::array_2d::Array2d;
+use crate::config::AlignerConfig;
+use crate::fragment::{self, NO_PAIR};
+
+/// Returns whether the given character is a hiragana or katakana letter that is read as itself.
+///
+/// Iteration marks and small ヵ/ヶ are excluded because they are read differently.
+pub fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{3094}' | '\u{30a1}'..='\u{30f4}' | 'ー')
+}
+
+/// Converts a katakana letter into the corresponding hiragana letter. Other characters are
+/// returned as is.
+pub fn to_hiragana(c: char) -> char {
+    if ('\u{30a1}'..='\u{30f6}').contains(&c) {
+        char::from_u32(u32::from(c) - 0x60).unwrap()
+    } else {
+        c
+    }
+}
+
+/// Returns whether the pair of fragments satisfies the kana identity: a surface fragment that
+/// contains kana is a single kana aligned to the same kana regardless of hiragana/katakana.
+fn is_identity(surface: &[char], reading: &[char]) -> bool {
+    if !surface.iter().copied().any(is_kana) {
+        return true;
+    }
+    matches!((surface, reading), (&[s], &[r]) if to_hiragana(s) == to_hiragana(r))
+}
+
+/// Removes edges of the lattice that violate the kana identity.
+///
+/// If the word cannot be aligned without such edges, e.g., when the reading is written
+/// differently from the kana in the surface, the lattice is left unchanged.
+pub fn restrict_lattice(
+    config: &AlignerConfig,
+    surface: &[char],
+    reading: &[char],
+    pair_ids: &mut [u32],
+) {
+    let mut reachable = Array2d::new(surface.len() + 1, reading.len() + 1);
+    *reachable.get_mut(0, 0).unwrap() = true;
+    let mut pair_id_iter = pair_ids.iter();
+    fragment::for_each_edge(config, surface.len(), reading.len(), |i, j, p, q| {
+        let &pair_id = pair_id_iter.next().unwrap();
+        if pair_id != NO_PAIR
+            && *reachable.get(i, j).unwrap()
+            && is_identity(&surface[i..p], &reading[j..q])
+        {
+            *reachable.get_mut(p, q).unwrap() = true;
+        }
+    });
+    if !*reachable.get(surface.len(), reading.len()).unwrap() {
+        return;
+    }
+    let mut pair_id_iter = pair_ids.iter_mut();
+    fragment::for_each_edge(config, surface.len(), reading.len(), |i, j, p, q| {
+        let pair_id = pair_id_iter.next().unwrap();
+        if !is_identity(&surface[i..p], &reading[j..q]) {
+            *pair_id = NO_PAIR;
+        }
+    });
+}
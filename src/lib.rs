@@ -10,113 +10,417 @@
 #[macro_use]
 extern crate alloc;
 
+#[cfg(feature = "std")]
+extern crate std;
+
 mod array_2d;
+mod config;
+mod constraints;
+mod fragment;
+mod kana;
 mod map;
+mod normalize;
+mod predictor;
+mod utils;
+
+pub mod errors;
+
+pub use config::{AlignerConfig, Normalization, Prior};
+pub use constraints::Constraints;
+pub use errors::{Error, Result};
+pub use predictor::{Prediction, Predictor};
 
 use alloc::{string::String, vec::Vec};
+use core::fmt::Write as _;
+
+#[cfg(feature = "std")]
+use std::io::{Read, Write};
 
 use hashbrown::{HashMap, HashSet};
 use vaporetto::{CharacterBoundary, Sentence};
 
 use array_2d::Array2d;
+use fragment::{FragmentTable, NO_PAIR};
 use map::{HashMap2, HashSet4};
+use normalize::NormalizedText;
+use utils::SliceReader;
+
+/// Magic number of the serialized [`PhonemeMap`].
+const PHONEME_MAP_MAGIC: &[u8] = b"RualignPhonemeMap\n";
+
+/// Version of the serialized [`PhonemeMap`] format.
+const PHONEME_MAP_VERSION: u32 = 1;
 
 fn logsumexp(a: f64, b: f64) -> f64 {
-    if a > b {
+    if a == f64::NEG_INFINITY {
+        // Avoids NaN caused by -inf - -inf.
+        b
+    } else if a > b {
         a + (b - a).exp().ln_1p()
     } else {
         b + (a - b).exp().ln_1p()
     }
 }
 
+/// Digamma function for a positive argument.
+fn digamma(mut x: f64) -> f64 {
+    let mut result = 0.0;
+    while x < 6.0 {
+        result -= x.recip();
+        x += 1.0;
+    }
+    // Asymptotic expansion
+    let x2 = x.powi(2).recip();
+    result + x.ln()
+        - 0.5 * x.recip()
+        - x2 * (1.0 / 12.0
+            - x2 * (1.0 / 120.0 - x2 * (1.0 / 252.0 - x2 * (1.0 / 240.0 - x2 / 132.0))))
+}
+
 fn log_square_error(a: f64, b: f64) -> f64 {
-    if a > b {
+    if a == b {
+        f64::NEG_INFINITY
+    } else if a > b {
         (a + (1.0 - (b - a).exp()).ln()) * 2.0
     } else {
         (b + (1.0 - (a - b).exp()).ln()) * 2.0
     }
 }
 
+/// Appends a fragment to a TSV line, escaping backslashes, tabs, and line breaks.
+fn push_tsv_field(tsv: &mut String, fragment: &[char]) {
+    for &c in fragment {
+        match c {
+            '\\' => tsv.push_str("\\\\"),
+            '\t' => tsv.push_str("\\t"),
+            '\n' => tsv.push_str("\\n"),
+            '\r' => tsv.push_str("\\r"),
+            _ => tsv.push(c),
+        }
+    }
+}
+
+/// Restores a fragment escaped by [`push_tsv_field()`]. Returns [`None`] if the field contains an
+/// unknown escape sequence.
+fn unescape_tsv_field(field: &str) -> Option<String> {
+    let mut fragment = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            fragment.push(match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            });
+        } else {
+            fragment.push(c);
+        }
+    }
+    Some(fragment)
+}
+
+/// Splits the original surface and reading at the end positions of the aligned fragments in the
+/// normalized characters.
+fn split_fragments<'a>(
+    surface: &NormalizedText<'a>,
+    reading: &NormalizedText<'a>,
+    alignments: &[(usize, usize)],
+) -> Vec<(&'a str, &'a str)> {
+    let (surface_text, reading_text) = (surface.text(), reading.text());
+    let surface_offsets: Vec<_> = surface_text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([surface_text.len()])
+        .collect();
+    let reading_offsets: Vec<_> = reading_text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain([reading_text.len()])
+        .collect();
+    let mut surface_start = 0;
+    let mut reading_start = 0;
+    let mut pairs = Vec::with_capacity(alignments.len());
+    for &(surface_end, reading_end) in alignments {
+        let surface_end = surface.original_pos(surface_end);
+        let reading_end = reading.original_pos(reading_end);
+        pairs.push((
+            &surface_text[surface_offsets[surface_start]..surface_offsets[surface_end]],
+            &reading_text[reading_offsets[reading_start]..reading_offsets[reading_end]],
+        ));
+        surface_start = surface_end;
+        reading_start = reading_end;
+    }
+    pairs
+}
+
+/// A node of an alignment path.
+#[derive(Clone, Copy)]
+struct PathNode {
+    i: usize,
+    j: usize,
+
+    // Score of the rest of the path from the node.
+    score: f64,
+
+    // Pair ID of the edge from the previous node, or `NO_PAIR` for the first node.
+    pair_id: u32,
+}
+
+/// A unique pair of a surface and a reading in the training data.
+struct Word {
+    surface: Vec<char>,
+    phoneme: Vec<char>,
+
+    // Number of occurrences.
+    count: usize,
+
+    // Fragment boundaries forced by constraints.
+    anchors: Vec<(usize, usize)>,
+}
+
+/// Statistics of a training iteration returned by [`Aligner::update()`] and
+/// [`Aligner::update_n_best()`].
+#[derive(Clone, Copy, Debug)]
+pub struct TrainingStats {
+    log_likelihood: f64,
+    num_active_pairs: usize,
+    num_unreachable_words: usize,
+    log_squared_error: f64,
+}
+
+impl TrainingStats {
+    /// Returns the log-likelihood of the training data under the scores before the update.
+    /// Unreachable words are excluded.
+    #[must_use]
+    pub const fn log_likelihood(&self) -> f64 {
+        self.log_likelihood
+    }
+
+    /// Returns the number of fragment pairs that have non-zero probabilities after the update.
+    #[must_use]
+    pub const fn num_active_pairs(&self) -> usize {
+        self.num_active_pairs
+    }
+
+    /// Returns the number of unique words in the training data that cannot be aligned under the
+    /// scores before the update.
+    #[must_use]
+    pub const fn num_unreachable_words(&self) -> usize {
+        self.num_unreachable_words
+    }
+
+    /// Returns the logarithm of the squared error of scores between before and after the update.
+    #[must_use]
+    pub const fn log_squared_error(&self) -> f64 {
+        self.log_squared_error
+    }
+}
+
 pub struct Aligner {
-    dataset: Vec<(Vec<char>, Vec<char>)>,
-    alphas: Array2d<f64>,
-    betas: Array2d<f64>,
-    scores: HashMap2<Vec<char>, Vec<char>, f64>,
+    dataset: Vec<Word>,
+    fragments: FragmentTable,
+
+    // Number of pairs that appear in the lattices of the training data. Such pairs have smaller
+    // IDs than pairs only given by `load_scores_tsv()`.
+    num_lattice_pairs: usize,
+
+    // Initial score of pairs.
+    init_score: f64,
+
+    config: AlignerConfig,
 }
 
 impl Aligner {
-    #[must_use]
-    pub fn new(sentences: &[Sentence], tag_index: usize) -> Self {
-        let mut dataset: Vec<(Vec<char>, Vec<char>)> = vec![];
-        for sentence in sentences {
-            for token in sentence.iter_tokens() {
-                let phoneme = token
-                    .tags()
-                    .get(tag_index)
-                    .and_then(Option::as_ref)
-                    .map_or("", |x| x.as_ref());
-                dataset.push((token.surface().chars().collect(), phoneme.chars().collect()));
-            }
+    /// Creates a new aligner from sentences. Readings are taken from the tag at `tag_index`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if the dataset is too large.
+    pub fn new(sentences: &[Sentence], tag_index: usize, config: AlignerConfig) -> Result<Self> {
+        Self::from_pairs(
+            sentences.iter().flat_map(|sentence| {
+                sentence.iter_tokens().map(move |token| {
+                    let phoneme = token
+                        .tags()
+                        .get(tag_index)
+                        .and_then(Option::as_ref)
+                        .map_or("", |x| x.as_ref());
+                    (token.surface(), phoneme)
+                })
+            }),
+            config,
+        )
+    }
+
+    /// Creates a new aligner from pairs of a surface and a reading, such as entries of a
+    /// pronunciation dictionary.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if the dataset is too large.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rualign::{Aligner, AlignerConfig};
+    ///
+    /// let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本", "ほん")];
+    /// let aligner = Aligner::from_pairs(pairs, AlignerConfig::new())?;
+    /// # Ok::<(), rualign::Error>(())
+    /// ```
+    pub fn from_pairs<I, S, R>(pairs: I, config: AlignerConfig) -> Result<Self>
+    where
+        I: IntoIterator<Item = (S, R)>,
+        S: AsRef<str>,
+        R: AsRef<str>,
+    {
+        let mut counts = HashMap::new();
+        for (surface, phoneme) in pairs {
+            let surface = config.normalize_surface(surface.as_ref()).into_chars();
+            let phoneme = config.normalize_reading(phoneme.as_ref()).into_chars();
+            *counts.entry((surface, phoneme)).or_insert(0) += 1;
         }
 
         // Initializes scores
-        let mut scores = HashMap2::new();
+        let mut fragments = FragmentTable::new();
         let mut cnt = 0;
-        for (surface, phoneme) in &dataset {
-            cnt += surface.len() * phoneme.len();
-        }
-        let init_score = -f64::from(u32::try_from(cnt).unwrap()).ln();
-        for (surface, phoneme) in &dataset {
-            for i in 0..surface.len() {
-                for j in 0..phoneme.len() + 1 {
-                    if i == 0 && j != 0 {
-                        continue;
-                    }
-                    for p in i + 1..surface.len() + 1 {
-                        for q in j..phoneme.len() + 1 {
-                            if p == surface.len() && q != phoneme.len() {
-                                continue;
-                            }
-                            scores.insert(
-                                surface[i..p].to_vec(),
-                                phoneme[j..q].to_vec(),
-                                init_score,
-                            );
-                        }
-                    }
+        for ((surface, phoneme), count) in &counts {
+            cnt += surface.len() * phoneme.len() * count;
+        }
+        let init_score = -f64::from(u32::try_from(cnt.max(1))?).ln();
+        let mut dataset = vec![];
+        for ((surface, phoneme), count) in counts {
+            fragments.insert_lattice(&config, &surface, &phoneme, init_score)?;
+            dataset.push(Word {
+                surface,
+                phoneme,
+                count,
+                anchors: vec![],
+            });
+        }
+
+        if config.two_phase_training {
+            // The first phase excludes deletions.
+            for pair_id in 0..fragments.len() {
+                if fragments.is_deletion(pair_id) {
+                    fragments.scores_mut()[pair_id] = f64::NEG_INFINITY;
                 }
             }
         }
 
-        Self {
+        Ok(Self {
             dataset,
-            alphas: Array2d::new(0, 0),
-            betas: Array2d::new(0, 0),
-            scores,
+            num_lattice_pairs: fragments.len(),
+            init_score,
+            fragments,
+            config,
+        })
+    }
+
+    /// Applies the given constraints to the training data.
+    ///
+    /// Forbidden pairs are removed from the model, and edges of the lattices that skip anchors
+    /// are removed. Words that cannot satisfy the constraints are ignored in training.
+    /// Constraints are accumulated if this function is called multiple times.
+    ///
+    /// Constraints are given in the original characters and normalized in the same way as the
+    /// training data.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidModel`] will be returned if an anchor is placed outside its word. In that
+    /// case, no constraints are applied.
+    /// [`Error::CastError`] will be returned if the model is too large.
+    pub fn apply_constraints(&mut self, constraints: &Constraints) -> Result<()> {
+        let constraints = constraints.normalize(&self.config)?;
+        let mut keep = vec![];
+        self.fragments.for_each(|surface, reading, _| {
+            keep.push(!constraints.is_forbidden(&surface, &reading));
+        });
+        self.retain_pairs(&keep)?;
+        for word in &mut self.dataset {
+            word.anchors
+                .extend_from_slice(constraints.anchors(&word.surface, &word.phoneme));
+        }
+        Ok(())
+    }
+
+    /// Returns pair IDs of the lattice of the given word in the order of
+    /// [`fragment::for_each_edge()`], restricted by the configuration and the constraints.
+    ///
+    /// Lattices are rebuilt each time instead of being kept for all words, so memory scales
+    /// with the number of distinct pairs rather than the total size of the lattices. This
+    /// includes the kana identity check, which costs another pass over the lattice. Pairs that
+    /// violate the kana identity are kept in the fragment table, since words falling back to
+    /// the unrestricted lattice, including those that only do so after anchors are added, still
+    /// use them.
+    fn word_lattice(&self, word: &Word) -> Result<Vec<u32>> {
+        let mut pair_ids = self
+            .fragments
+            .lattice(&self.config, &word.surface, &word.phoneme)?;
+        // Pairs only given by `load_scores_tsv()` are not trained.
+        let num_lattice_pairs = u32::try_from(self.num_lattice_pairs)?;
+        for pair_id in &mut pair_ids {
+            if *pair_id >= num_lattice_pairs {
+                *pair_id = NO_PAIR;
+            }
+        }
+        if !word.anchors.is_empty() {
+            let mut pair_ids = pair_ids.iter_mut();
+            fragment::for_each_edge(
+                &self.config,
+                word.surface.len(),
+                word.phoneme.len(),
+                |i, j, p, q| {
+                    let pair_id = pair_ids.next().unwrap();
+                    let skips_anchor = word
+                        .anchors
+                        .iter()
+                        .any(|&(a, b)| !(p <= a && q <= b || i >= a && j >= b));
+                    if skips_anchor {
+                        *pair_id = NO_PAIR;
+                    }
+                },
+            );
+        }
+        // Applied after the anchors so that a word falls back to the lattice without the kana
+        // identity if it cannot be aligned under both of them.
+        if self.config.kana_identity {
+            kana::restrict_lattice(&self.config, &word.surface, &word.phoneme, &mut pair_ids);
         }
+        Ok(pair_ids)
     }
 
     fn calculate_alphas(
-        surface: &[char],
-        phoneme: &[char],
-        scores: &HashMap2<Vec<char>, Vec<char>, f64>,
+        config: &AlignerConfig,
+        surface_len: usize,
+        phoneme_len: usize,
+        pair_ids: &[u32],
+        scores: &[f64],
         alphas: &mut Array2d<f64>,
-    ) {
-        alphas.resize(surface.len() + 1, phoneme.len() + 1, f64::NEG_INFINITY);
+    ) -> Result<()> {
+        alphas.resize(surface_len + 1, phoneme_len + 1, f64::NEG_INFINITY);
         alphas.fill(f64::NEG_INFINITY);
         *alphas.get_mut(0, 0).unwrap() = 0.0;
-        for i in 0..surface.len() {
-            for j in 0..phoneme.len() + 1 {
+        let mut pair_ids = pair_ids.iter();
+        for i in 0..surface_len {
+            for j in 0..phoneme_len + 1 {
                 if i == 0 && j != 0 {
                     continue;
                 }
-                for p in i + 1..surface.len() + 1 {
-                    for q in j..phoneme.len() + 1 {
-                        if p == surface.len() && q != phoneme.len() {
+                for p in config.surface_ends(i, surface_len) {
+                    for q in config.reading_ends(j, phoneme_len) {
+                        if p == surface_len && q != phoneme_len {
                             continue;
                         }
-                        let score = *scores.get(&surface[i..p], &phoneme[j..q]).unwrap();
-                        let distance = f64::from(u32::try_from(p - i + (q - j).max(1)).unwrap());
+                        let pair_id = *pair_ids.next().unwrap();
+                        if pair_id == NO_PAIR {
+                            continue;
+                        }
+                        let score = scores[usize::try_from(pair_id)?];
+                        let distance = config.distance(i, j, p, q)?;
                         *alphas.get_mut(p, q).unwrap() = logsumexp(
                             *alphas.get(p, q).unwrap(),
                             score.mul_add(distance, *alphas.get(i, j).unwrap()),
@@ -125,29 +429,37 @@ impl Aligner {
                 }
             }
         }
+        Ok(())
     }
 
     fn calculate_betas(
-        surface: &[char],
-        phoneme: &[char],
-        scores: &HashMap2<Vec<char>, Vec<char>, f64>,
+        config: &AlignerConfig,
+        surface_len: usize,
+        phoneme_len: usize,
+        pair_ids: &[u32],
+        scores: &[f64],
         betas: &mut Array2d<f64>,
-    ) {
-        betas.resize(surface.len() + 1, phoneme.len() + 1, f64::NEG_INFINITY);
+    ) -> Result<()> {
+        betas.resize(surface_len + 1, phoneme_len + 1, f64::NEG_INFINITY);
         betas.fill(f64::NEG_INFINITY);
-        *betas.get_mut(surface.len(), phoneme.len()).unwrap() = 0.0;
-        for i in (0..surface.len()).rev() {
-            for j in (0..phoneme.len() + 1).rev() {
+        *betas.get_mut(surface_len, phoneme_len).unwrap() = 0.0;
+        let mut pair_ids = pair_ids.iter().rev();
+        for i in (0..surface_len).rev() {
+            for j in (0..phoneme_len + 1).rev() {
                 if i == 0 && j != 0 {
                     continue;
                 }
-                for p in (i + 1..surface.len() + 1).rev() {
-                    for q in (j..phoneme.len() + 1).rev() {
-                        if p == surface.len() && q != phoneme.len() {
+                for p in config.surface_ends(i, surface_len).rev() {
+                    for q in config.reading_ends(j, phoneme_len).rev() {
+                        if p == surface_len && q != phoneme_len {
                             continue;
                         }
-                        let score = *scores.get(&surface[i..p], &phoneme[j..q]).unwrap();
-                        let distance = f64::from(u32::try_from(p - i + (q - j).max(1)).unwrap());
+                        let pair_id = *pair_ids.next().unwrap();
+                        if pair_id == NO_PAIR {
+                            continue;
+                        }
+                        let score = scores[usize::try_from(pair_id)?];
+                        let distance = config.distance(i, j, p, q)?;
                         *betas.get_mut(i, j).unwrap() = logsumexp(
                             *betas.get(i, j).unwrap(),
                             score.mul_add(distance, *betas.get(p, q).unwrap()),
@@ -156,72 +468,91 @@ impl Aligner {
                 }
             }
         }
+        Ok(())
     }
 
-    fn calculate_gammas<'a, 'b>(
-        surface: &'a [char],
-        phoneme: &'b [char],
-        scores: &HashMap2<Vec<char>, Vec<char>, f64>,
+    /// Accumulates posterior probabilities of the word that appears `exp(log_count)` times.
+    #[allow(clippy::too_many_arguments)]
+    fn calculate_gammas(
+        config: &AlignerConfig,
+        surface_len: usize,
+        phoneme_len: usize,
+        pair_ids: &[u32],
+        log_count: f64,
+        scores: &[f64],
         alphas: &Array2d<f64>,
         betas: &Array2d<f64>,
-        gammas: &mut HashMap2<&'a [char], &'b [char], f64>,
-    ) {
+        gammas: &mut [f64],
+    ) -> Result<()> {
         let score_sum = *betas.get(0, 0).unwrap();
-        for i in (0..surface.len()).rev() {
-            for j in (0..phoneme.len() + 1).rev() {
+        if score_sum == f64::NEG_INFINITY {
+            // The word cannot be aligned under the configuration.
+            return Ok(());
+        }
+        let mut pair_ids = pair_ids.iter();
+        for i in 0..surface_len {
+            for j in 0..phoneme_len + 1 {
                 if i == 0 && j != 0 {
                     continue;
                 }
-                for p in (i + 1..surface.len() + 1).rev() {
-                    for q in (j..phoneme.len() + 1).rev() {
-                        if p == surface.len() && q != phoneme.len() {
+                for p in config.surface_ends(i, surface_len) {
+                    for q in config.reading_ends(j, phoneme_len) {
+                        if p == surface_len && q != phoneme_len {
+                            continue;
+                        }
+                        let pair_id = *pair_ids.next().unwrap();
+                        if pair_id == NO_PAIR {
                             continue;
                         }
-                        let surface_slice = &surface[i..p];
-                        let phoneme_slice = &phoneme[j..q];
-                        let score = *scores.get(surface_slice, phoneme_slice).unwrap();
-                        let distance = f64::from(u32::try_from(p - i + (q - j).max(1)).unwrap());
-                        let gamma = logsumexp(
-                            *gammas
-                                .get(surface_slice, phoneme_slice)
-                                .unwrap_or(&f64::NEG_INFINITY),
-                            score.mul_add(
+                        let pair_id = usize::try_from(pair_id)?;
+                        let distance = config.distance(i, j, p, q)?;
+                        gammas[pair_id] = logsumexp(
+                            gammas[pair_id],
+                            scores[pair_id].mul_add(
                                 distance,
                                 *alphas.get(i, j).unwrap() + *betas.get(p, q).unwrap(),
-                            ) - score_sum,
+                            ) - score_sum
+                                + log_count,
                         );
-                        gammas.insert(surface_slice, phoneme_slice, gamma);
                     }
                 }
             }
         }
+        Ok(())
     }
 
-    fn search_best_path<'a>(
-        scores: &HashMap2<Vec<char>, Vec<char>, f64>,
-        surface: &'a [char],
-        phoneme: &'a [char],
+    /// Searches the best alignment path.
+    ///
+    /// Edges whose pair ID is [`NO_PAIR`] are treated as impossible. If there is no path,
+    /// `Ok(None)` is returned.
+    fn search_best_path(
+        config: &AlignerConfig,
+        surface_len: usize,
+        phoneme_len: usize,
+        pair_ids: &[u32],
+        scores: &[f64],
         best_nodes: &mut Array2d<(f64, usize, usize)>,
-    ) -> Vec<(usize, usize)> {
+    ) -> Result<Option<Vec<(usize, usize)>>> {
         best_nodes.fill((f64::NEG_INFINITY, 0, 0));
-        best_nodes.resize(
-            surface.len() + 1,
-            phoneme.len() + 1,
-            (f64::NEG_INFINITY, 0, 0),
-        );
-        best_nodes.get_mut(surface.len(), phoneme.len()).unwrap().0 = 0.0;
-        for i in (0..surface.len()).rev() {
-            for j in (0..phoneme.len() + 1).rev() {
+        best_nodes.resize(surface_len + 1, phoneme_len + 1, (f64::NEG_INFINITY, 0, 0));
+        best_nodes.get_mut(surface_len, phoneme_len).unwrap().0 = 0.0;
+        let mut pair_ids = pair_ids.iter().rev();
+        for i in (0..surface_len).rev() {
+            for j in (0..phoneme_len + 1).rev() {
                 if i == 0 && j != 0 {
                     continue;
                 }
-                for p in (i + 1..surface.len() + 1).rev() {
-                    for q in (j..phoneme.len() + 1).rev() {
-                        if p == surface.len() && q != phoneme.len() {
+                for p in config.surface_ends(i, surface_len).rev() {
+                    for q in config.reading_ends(j, phoneme_len).rev() {
+                        if p == surface_len && q != phoneme_len {
+                            continue;
+                        }
+                        let pair_id = *pair_ids.next().unwrap();
+                        if pair_id == NO_PAIR {
                             continue;
                         }
-                        let score = *scores.get(&surface[i..p], &phoneme[j..q]).unwrap();
-                        let distance = f64::from(u32::try_from(p - i + (q - j).max(1)).unwrap());
+                        let score = scores[usize::try_from(pair_id)?];
+                        let distance = config.distance(i, j, p, q)?;
                         let new_score = score.mul_add(distance, best_nodes.get(p, q).unwrap().0);
                         let current_best_node = best_nodes.get_mut(i, j).unwrap();
                         if current_best_node.0 < new_score {
@@ -231,15 +562,168 @@ impl Aligner {
                 }
             }
         }
+        if best_nodes.get(0, 0).unwrap().0 == f64::NEG_INFINITY {
+            return Ok(None);
+        }
         let mut result = vec![];
         let (mut i, mut j) = (0, 0);
-        while i != surface.len() && j != phoneme.len() {
+        while i != surface_len || j != phoneme_len {
             let (_, next_i, next_j) = *best_nodes.get(i, j).unwrap();
             result.push((next_i, next_j));
             i = next_i;
             j = next_j;
         }
-        result
+        Ok(Some(result))
+    }
+
+    /// Searches the `n` best alignment paths in descending order of scores.
+    ///
+    /// Edges whose pair ID is [`NO_PAIR`] are treated as impossible. Ties are broken in the same
+    /// way as [`Aligner::search_best_path()`], so the first path is the same as its result.
+    fn search_n_best_paths(
+        config: &AlignerConfig,
+        surface_len: usize,
+        phoneme_len: usize,
+        pair_ids: &[u32],
+        scores: &[f64],
+        n: usize,
+    ) -> Result<Vec<Vec<PathNode>>> {
+        if n == 0 {
+            return Ok(vec![]);
+        }
+        // Each node holds the n best paths from the node to the end as tuples of the score, the
+        // next node, the rank of the path at the next node, and the pair ID of the edge.
+        let mut nodes: Array2d<Vec<_>> = Array2d::new(surface_len + 1, phoneme_len + 1);
+        nodes.get_mut(surface_len, phoneme_len).unwrap().push((
+            0.0,
+            surface_len,
+            phoneme_len,
+            0,
+            NO_PAIR,
+        ));
+        let mut candidates = vec![];
+        let mut pair_ids = pair_ids.iter().rev();
+        for i in (0..surface_len).rev() {
+            for j in (0..phoneme_len + 1).rev() {
+                if i == 0 && j != 0 {
+                    continue;
+                }
+                candidates.clear();
+                for p in config.surface_ends(i, surface_len).rev() {
+                    for q in config.reading_ends(j, phoneme_len).rev() {
+                        if p == surface_len && q != phoneme_len {
+                            continue;
+                        }
+                        let pair_id = *pair_ids.next().unwrap();
+                        if pair_id == NO_PAIR {
+                            continue;
+                        }
+                        let score = scores[usize::try_from(pair_id)?];
+                        let distance = config.distance(i, j, p, q)?;
+                        for (rank, &(next_score, _, _, _, _)) in
+                            nodes.get(p, q).unwrap().iter().enumerate()
+                        {
+                            candidates.push((
+                                score.mul_add(distance, next_score),
+                                p,
+                                q,
+                                rank,
+                                pair_id,
+                            ));
+                        }
+                    }
+                }
+                // The stable sort keeps the first candidate among ties.
+                candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+                candidates.truncate(n);
+                nodes.get_mut(i, j).unwrap().extend_from_slice(&candidates);
+            }
+        }
+        let mut results = vec![];
+        for (rank, &(score, _, _, _, _)) in nodes.get(0, 0).unwrap().iter().enumerate() {
+            let mut path = vec![PathNode {
+                i: 0,
+                j: 0,
+                score,
+                pair_id: NO_PAIR,
+            }];
+            let (mut i, mut j, mut rank) = (0, 0, rank);
+            while i != surface_len || j != phoneme_len {
+                let (_, next_i, next_j, next_rank, pair_id) = nodes.get(i, j).unwrap()[rank];
+                i = next_i;
+                j = next_j;
+                rank = next_rank;
+                path.push(PathNode {
+                    i,
+                    j,
+                    score: nodes.get(i, j).unwrap()[rank].0,
+                    pair_id,
+                });
+            }
+            results.push(path);
+        }
+        Ok(results)
+    }
+
+    /// Returns the `n` best alignments of the given pair using the given fragment scores.
+    fn n_best_alignments<'a>(
+        config: &AlignerConfig,
+        fragments: &FragmentTable,
+        surface: &'a str,
+        reading: &'a str,
+        n: usize,
+    ) -> Result<Vec<Alignment<'a>>> {
+        let surface = config.normalize_surface(surface);
+        let reading = config.normalize_reading(reading);
+        let (surface_chars, reading_chars) = (surface.chars(), reading.chars());
+        let pair_ids = fragments.lattice(config, surface_chars, reading_chars)?;
+        let paths = Self::search_n_best_paths(
+            config,
+            surface_chars.len(),
+            reading_chars.len(),
+            &pair_ids,
+            fragments.scores(),
+            n,
+        )?;
+        let mut alphas = Array2d::new(0, 0);
+        let mut betas = Array2d::new(0, 0);
+        if !paths.is_empty() {
+            Self::calculate_alphas(
+                config,
+                surface_chars.len(),
+                reading_chars.len(),
+                &pair_ids,
+                fragments.scores(),
+                &mut alphas,
+            )?;
+            Self::calculate_betas(
+                config,
+                surface_chars.len(),
+                reading_chars.len(),
+                &pair_ids,
+                fragments.scores(),
+                &mut betas,
+            )?;
+        }
+        Ok(paths
+            .into_iter()
+            .map(|path| Alignment::new(&surface, &reading, &path, &alphas, &betas))
+            .collect())
+    }
+
+    /// Returns the `n` best alignments of the given surface and reading under the current scores
+    /// in descending order of scores.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if the input is too long.
+    pub fn align_n_best<'a>(
+        &self,
+        surface: &'a str,
+        reading: &'a str,
+        n: usize,
+    ) -> Result<Vec<Alignment<'a>>> {
+        Self::n_best_alignments(&self.config, &self.fragments, surface, reading, n)
     }
 
     fn merge_phonemes(phoneme_map: &mut HashMap2<Vec<char>, Vec<char>, Vec<(usize, usize)>>) {
@@ -320,12 +804,16 @@ impl Aligner {
         });
     }
 
-    pub fn update(&mut self) -> f64 {
-        // Scores calculated in E-step
-        let mut gammas = HashMap2::new();
-
-        // E-step
-        for (surface, phoneme) in &self.dataset {
+    /// Runs the forward-backward algorithm on the given data and accumulates the posterior
+    /// probabilities of fragment pairs into `gammas`.
+    fn accumulate_gammas(&self, dataset: &[Word], gammas: &mut [f64]) -> Result<(f64, usize)> {
+        let config = &self.config;
+        let scores = self.fragments.scores();
+        let mut alphas = Array2d::new(0, 0);
+        let mut betas = Array2d::new(0, 0);
+        let mut log_likelihood = 0.0;
+        let mut num_unreachable_words = 0;
+        for word in dataset {
             // The original algorithm divides training into the first and second parts to
             // prevent the excessive occurance of deletion characters from being generated
             // caused by the city block distance. The first part uses the EM algorithm to train
@@ -335,82 +823,918 @@ impl Aligner {
             // In contrast, this implementation adds the cost corresponding to the deletion
             // characters to the city block distance from the beginning to simplify the
             // algorithm while preventing the excessive occurrence of deletion characters.
-            Self::calculate_alphas(surface, phoneme, &self.scores, &mut self.alphas);
-            Self::calculate_betas(surface, phoneme, &self.scores, &mut self.betas);
+            // The original schedule is enabled by `AlignerConfig::two_phase_training()`.
+            let surface_len = word.surface.len();
+            let phoneme_len = word.phoneme.len();
+            let pair_ids = self.word_lattice(word)?;
+            Self::calculate_alphas(
+                config,
+                surface_len,
+                phoneme_len,
+                &pair_ids,
+                scores,
+                &mut alphas,
+            )?;
+            Self::calculate_betas(
+                config,
+                surface_len,
+                phoneme_len,
+                &pair_ids,
+                scores,
+                &mut betas,
+            )?;
+            let count = f64::from(u32::try_from(word.count)?);
+            let score_sum = *betas.get(0, 0).unwrap();
+            if score_sum == f64::NEG_INFINITY {
+                num_unreachable_words += 1;
+            } else {
+                log_likelihood += count * score_sum;
+            }
             Self::calculate_gammas(
-                surface,
-                phoneme,
-                &self.scores,
-                &self.alphas,
-                &self.betas,
-                &mut gammas,
-            );
+                config,
+                surface_len,
+                phoneme_len,
+                &pair_ids,
+                count.ln(),
+                scores,
+                &alphas,
+                &betas,
+                gammas,
+            )?;
         }
+        Ok((log_likelihood, num_unreachable_words))
+    }
+
+    /// Parallel version of [`Aligner::accumulate_gammas()`].
+    ///
+    /// The dataset is split into chunks, and posterior probabilities accumulated by each thread
+    /// are merged at the end.
+    #[cfg(feature = "parallel")]
+    fn accumulate_gammas_parallel(&self, gammas: &mut [f64]) -> Result<(f64, usize)> {
+        let chunk_size = self
+            .dataset
+            .len()
+            .div_ceil(self.config.get_num_threads())
+            .max(1);
+        let num_pairs = gammas.len();
+        std::thread::scope(|s| {
+            let handles: Vec<_> = self
+                .dataset
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        let mut local_gammas = vec![f64::NEG_INFINITY; num_pairs];
+                        let stats = self.accumulate_gammas(chunk, &mut local_gammas)?;
+                        Ok::<_, Error>((local_gammas, stats))
+                    })
+                })
+                .collect();
+            let mut log_likelihood = 0.0;
+            let mut num_unreachable_words = 0;
+            for handle in handles {
+                let (local_gammas, (local_log_likelihood, local_num_unreachable_words)) = handle
+                    .join()
+                    .unwrap_or_else(|e| std::panic::resume_unwind(e))?;
+                for (gamma, v) in gammas.iter_mut().zip(local_gammas) {
+                    *gamma = logsumexp(*gamma, v);
+                }
+                log_likelihood += local_log_likelihood;
+                num_unreachable_words += local_num_unreachable_words;
+            }
+            Ok((log_likelihood, num_unreachable_words))
+        })
+    }
+
+    /// Runs one iteration of the EM algorithm and returns statistics of the iteration.
+    ///
+    /// If the **crate feature** `parallel` is enabled, the E-step runs in multiple threads. The
+    /// number of threads can be set by `AlignerConfig::num_threads()`.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if a token is too long.
+    pub fn update(&mut self) -> Result<TrainingStats> {
+        // Scores calculated in E-step
+        let mut gammas = vec![f64::NEG_INFINITY; self.num_lattice_pairs];
+
+        // E-step
+        #[cfg(not(feature = "parallel"))]
+        let (log_likelihood, num_unreachable_words) =
+            self.accumulate_gammas(&self.dataset, &mut gammas)?;
+        #[cfg(feature = "parallel")]
+        let (log_likelihood, num_unreachable_words) =
+            self.accumulate_gammas_parallel(&mut gammas)?;
 
         // M-step
+        self.maximize(gammas, log_likelihood, num_unreachable_words)
+    }
+
+    /// Runs one iteration of the n-best Viterbi training and returns statistics of the iteration.
+    ///
+    /// Occurrences of pairs are counted on the `n` best paths of each word, weighted by the
+    /// probabilities of the paths normalized among them. The log-likelihood in the statistics is
+    /// also calculated only from the `n` best paths. Pairs of an empty reading whose score is
+    /// -inf, such as those excluded by the first phase of the two-phase training, are searched
+    /// with the initial score. This is the second phase of the two-phase training enabled by
+    /// [`AlignerConfig::two_phase_training()`].
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if a token is too long.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn update_n_best(&mut self, n: usize) -> Result<TrainingStats> {
+        assert!(n != 0, "n must be positive");
+        let mut scores = self.fragments.scores().to_vec();
+        for (pair_id, score) in scores.iter_mut().enumerate() {
+            if *score == f64::NEG_INFINITY && self.fragments.is_deletion(pair_id) {
+                *score = self.init_score;
+            }
+        }
+
+        let mut gammas = vec![f64::NEG_INFINITY; self.num_lattice_pairs];
+        let mut log_likelihood = 0.0;
+        let mut num_unreachable_words = 0;
+        for word in &self.dataset {
+            let paths = Self::search_n_best_paths(
+                &self.config,
+                word.surface.len(),
+                word.phoneme.len(),
+                &self.word_lattice(word)?,
+                &scores,
+                n,
+            )?;
+            if paths.is_empty() {
+                num_unreachable_words += 1;
+                continue;
+            }
+            let mut score_sum = f64::NEG_INFINITY;
+            for path in &paths {
+                score_sum = logsumexp(score_sum, path[0].score);
+            }
+            let count = f64::from(u32::try_from(word.count)?);
+            log_likelihood += count * score_sum;
+            for path in &paths {
+                let weight = path[0].score - score_sum + count.ln();
+                for node in &path[1..] {
+                    let pair_id = usize::try_from(node.pair_id)?;
+                    gammas[pair_id] = logsumexp(gammas[pair_id], weight);
+                }
+            }
+        }
+
+        self.maximize(gammas, log_likelihood, num_unreachable_words)
+    }
+
+    /// Updates scores with the accumulated posterior probabilities and returns statistics of the
+    /// iteration.
+    fn maximize(
+        &mut self,
+        gammas: Vec<f64>,
+        log_likelihood: f64,
+        num_unreachable_words: usize,
+    ) -> Result<TrainingStats> {
+        let (log_prior_count, variational) = match self.config.prior {
+            Prior::None => (f64::NEG_INFINITY, false),
+            Prior::Additive(alpha) => (alpha.ln(), false),
+            Prior::VariationalBayes(alpha) => (alpha.ln(), true),
+        };
+
+        // Adds pseudo-counts to the expected counts. Pairs with zero probability that gain no
+        // expected count, such as deletions in the first phase of the two-phase training, stay
+        // at zero.
+        let counts: Vec<_> = gammas
+            .iter()
+            .zip(self.fragments.scores())
+            .map(|(&v, &score)| {
+                if v == f64::NEG_INFINITY && score == f64::NEG_INFINITY {
+                    f64::NEG_INFINITY
+                } else {
+                    logsumexp(v, log_prior_count)
+                }
+            })
+            .collect();
+
+        // Pairs are normalized in groups of the same key.
+        let normalization = self.config.normalization;
+        let fragments = &self.fragments;
+        let group = |pair_id| {
+            let (surface_id, reading_id) = fragments.pair(pair_id);
+            match normalization {
+                Normalization::Joint => 0,
+                Normalization::Surface => surface_id,
+                Normalization::Reading => reading_id,
+            }
+        };
+        let mut count_sums = HashMap::new();
+        for (pair_id, &v) in counts.iter().enumerate() {
+            let count_sum = count_sums
+                .entry(group(pair_id))
+                .or_insert(f64::NEG_INFINITY);
+            *count_sum = logsumexp(*count_sum, v);
+        }
+        let groups: Vec<_> = (0..counts.len()).map(group).collect();
+
         let mut diff_total = f64::NEG_INFINITY;
-        let mut gamma_sum = f64::NEG_INFINITY;
-        gammas.for_each(|(_, _, &v)| {
-            gamma_sum = logsumexp(gamma_sum, v);
-        });
-        gammas.for_each(|(&k1, &k2, &v)| {
-            let score = self.scores.get_mut(k1, k2).unwrap();
-            diff_total = logsumexp(diff_total, log_square_error(v - gamma_sum, *score));
-            *score = v - gamma_sum;
-        });
+        for ((score, v), group) in self
+            .fragments
+            .scores_mut()
+            .iter_mut()
+            .zip(counts)
+            .zip(groups)
+        {
+            let count_sum = count_sums[&group];
+            // A group without any expected count, e.g., pairs of a surface fragment that is
+            // excluded from all lattices, keeps zero probability.
+            let new_score = if v == f64::NEG_INFINITY || count_sum == f64::NEG_INFINITY {
+                f64::NEG_INFINITY
+            } else if variational {
+                digamma(v.exp()) - digamma(count_sum.exp())
+            } else {
+                v - count_sum
+            };
+            diff_total = logsumexp(diff_total, log_square_error(new_score, *score));
+            *score = new_score;
+        }
+        if self.config.pruning_threshold > 0.0 {
+            self.prune()?;
+        }
+        let num_active_pairs = self.fragments.scores()[..self.num_lattice_pairs]
+            .iter()
+            .filter(|&&score| score != f64::NEG_INFINITY)
+            .count();
+        Ok(TrainingStats {
+            log_likelihood,
+            num_active_pairs,
+            num_unreachable_words,
+            log_squared_error: diff_total,
+        })
+    }
+
+    /// Removes pairs in the lattices whose probabilities are below the pruning threshold.
+    fn prune(&mut self) -> Result<()> {
+        let threshold = self.config.pruning_threshold.ln();
+        let keep: Vec<_> = self
+            .fragments
+            .scores()
+            .iter()
+            .enumerate()
+            .map(|(pair_id, &score)| {
+                // Pairs only given by `load_scores_tsv()` are not trained, so they are kept.
+                pair_id >= self.num_lattice_pairs
+                    || score >= threshold
+                    || (self.config.two_phase_training && self.fragments.is_deletion(pair_id))
+            })
+            .collect();
+        self.retain_pairs(&keep)
+    }
 
-        diff_total
+    /// Removes pairs whose values in `keep` are false from the model.
+    fn retain_pairs(&mut self, keep: &[bool]) -> Result<()> {
+        let new_ids = self.fragments.retain(|pair_id| keep[pair_id])?;
+        self.num_lattice_pairs = new_ids[..self.num_lattice_pairs]
+            .iter()
+            .filter(|&&pair_id| pair_id != NO_PAIR)
+            .count();
+        Ok(())
     }
 
+    /// Exports the fragment scores as TSV.
+    ///
+    /// Each line contains a surface fragment, a reading fragment, and the log probability of the
+    /// pair, separated by tabs. Backslashes, tabs, and line breaks in the fragments are escaped
+    /// as `\\`, `\t`, `\n`, and `\r`. Lines are sorted by the surface and the reading.
     #[must_use]
-    pub fn finalize(self) -> PhonemeMap {
+    pub fn scores_to_tsv(&self) -> String {
+        let mut entries = vec![];
+        self.fragments.for_each(|surface, phoneme, score| {
+            entries.push((surface, phoneme, score));
+        });
+        entries.sort_unstable_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        let mut tsv = String::new();
+        for (surface, phoneme, score) in entries {
+            push_tsv_field(&mut tsv, &surface);
+            tsv.push('\t');
+            push_tsv_field(&mut tsv, &phoneme);
+            writeln!(&mut tsv, "\t{score}").unwrap();
+        }
+        tsv
+    }
+
+    /// Exports the fragment scores as TSV.
+    ///
+    /// See [`Aligner::scores_to_tsv()`] for the format.
+    ///
+    /// # Errors
+    ///
+    /// When the writer generates an error, it will be returned as is.
+    #[cfg(feature = "std")]
+    pub fn write_scores<W>(&self, mut wtr: W) -> Result<()>
+    where
+        W: Write,
+    {
+        wtr.write_all(self.scores_to_tsv().as_bytes())?;
+        Ok(())
+    }
+
+    /// Overwrites the fragment scores with the ones in the given TSV.
+    ///
+    /// The format is the same as [`Aligner::scores_to_tsv()`]. Empty lines are ignored. Pairs
+    /// that do not appear in the TSV keep their current scores, so a table exported from another
+    /// aligner can be used to warm-start training.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidModel`] will be returned if a line is malformed or a score is neither a
+    /// non-positive finite number nor -inf. In that case, the scores are left unchanged.
+    pub fn load_scores_tsv(&mut self, tsv: &str) -> Result<()> {
+        let mut entries: Vec<(Vec<_>, Vec<_>, _)> = vec![];
+        for (i, line) in tsv.lines().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let mut cols = line.split('\t');
+            let (Some(surface), Some(phoneme), Some(score), None) =
+                (cols.next(), cols.next(), cols.next(), cols.next())
+            else {
+                return Err(Error::invalid_model(format!(
+                    "line {}: expected 3 columns",
+                    i + 1
+                )));
+            };
+            if surface.is_empty() {
+                return Err(Error::invalid_model(format!(
+                    "line {}: empty surface",
+                    i + 1
+                )));
+            }
+            let (Some(surface), Some(phoneme)) =
+                (unescape_tsv_field(surface), unescape_tsv_field(phoneme))
+            else {
+                return Err(Error::invalid_model(format!(
+                    "line {}: invalid escape sequence",
+                    i + 1
+                )));
+            };
+            // A log probability is at most 0.
+            let score: f64 = score
+                .parse()
+                .ok()
+                .filter(|score: &f64| {
+                    *score == f64::NEG_INFINITY || score.is_finite() && *score <= 0.0
+                })
+                .ok_or_else(|| Error::invalid_model(format!("line {}: invalid score", i + 1)))?;
+            entries.push((
+                self.config.normalize_surface(&surface).into_chars(),
+                self.config.normalize_reading(&phoneme).into_chars(),
+                score,
+            ));
+        }
+        for (surface, phoneme, score) in entries {
+            self.fragments.insert(&surface, &phoneme, score)?;
+        }
+        Ok(())
+    }
+
+    /// Overwrites the fragment scores with the ones read from the given TSV reader.
+    ///
+    /// See [`Aligner::load_scores_tsv()`] for details.
+    ///
+    /// # Errors
+    ///
+    /// When the reader generates an error, it will be returned as is.
+    /// [`Error::InvalidModel`] will be returned if a line is malformed.
+    #[cfg(feature = "std")]
+    pub fn read_scores<R>(&mut self, mut rdr: R) -> Result<()>
+    where
+        R: Read,
+    {
+        let mut tsv = String::new();
+        rdr.read_to_string(&mut tsv)?;
+        self.load_scores_tsv(&tsv)
+    }
+
+    /// Finalizes training and returns the phoneme map.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if a token is too long.
+    pub fn finalize(mut self) -> Result<PhonemeMap> {
         // Searches the best paths
         let mut best_nodes = Array2d::new(0, 0);
         let mut phoneme_map = HashMap2::new();
-        for (surface, phoneme) in self.dataset {
-            if phoneme_map.contains_key(&surface, &phoneme) {
-                continue;
-            }
-            let result = Self::search_best_path(&self.scores, &surface, &phoneme, &mut best_nodes);
-            phoneme_map.insert(surface, phoneme, result);
+        for word in core::mem::take(&mut self.dataset) {
+            let result = Self::search_best_path(
+                &self.config,
+                word.surface.len(),
+                word.phoneme.len(),
+                &self.word_lattice(&word)?,
+                self.fragments.scores(),
+                &mut best_nodes,
+            )?
+            .unwrap_or_else(|| vec![(word.surface.len(), word.phoneme.len())]);
+            phoneme_map.insert(word.surface, word.phoneme, result);
         }
 
-        Self::merge_phonemes(&mut phoneme_map);
+        if self.config.merge {
+            Self::merge_phonemes(&mut phoneme_map);
+        }
 
-        PhonemeMap { phoneme_map }
+        Ok(PhonemeMap {
+            phoneme_map,
+            fragments: self.fragments,
+            config: self.config,
+        })
     }
 }
 
+/// An alignment with its score and posterior probabilities of the segments.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Alignment<'a> {
+    segments: Vec<(&'a str, &'a str)>,
+    posteriors: Vec<f64>,
+    score: f64,
+}
+
+impl<'a> Alignment<'a> {
+    /// Creates an alignment of the given path. Posterior probabilities are calculated from the
+    /// forward and backward scores of the word.
+    fn new(
+        surface: &NormalizedText<'a>,
+        reading: &NormalizedText<'a>,
+        path: &[PathNode],
+        alphas: &Array2d<f64>,
+        betas: &Array2d<f64>,
+    ) -> Self {
+        let ends: Vec<_> = path[1..].iter().map(|node| (node.i, node.j)).collect();
+        let score_sum = betas.get(0, 0).copied().unwrap_or(f64::NEG_INFINITY);
+        let posteriors = path
+            .windows(2)
+            .map(|nodes| {
+                if score_sum == f64::NEG_INFINITY {
+                    // The word cannot be aligned under the scores.
+                    return 0.0;
+                }
+                let (start, end) = (nodes[0], nodes[1]);
+                // The probability of the sub-path, which is a single edge unless fragments are
+                // merged.
+                (*alphas.get(start.i, start.j).unwrap() + start.score - end.score
+                    + *betas.get(end.i, end.j).unwrap()
+                    - score_sum)
+                    .exp()
+                    .min(1.0)
+            })
+            .collect();
+        Self {
+            segments: split_fragments(surface, reading, &ends),
+            posteriors,
+            score: path[0].score,
+        }
+    }
+
+    /// Returns pairs of the surface fragments and the reading fragments.
+    #[must_use]
+    pub fn segments(&self) -> &[(&'a str, &'a str)] {
+        &self.segments
+    }
+
+    /// Returns the posterior probability of each segment within the word.
+    #[must_use]
+    pub fn posteriors(&self) -> &[f64] {
+        &self.posteriors
+    }
+
+    /// Returns the log score of the path.
+    #[must_use]
+    pub const fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// Specifies how tags other than readings are assigned to the tokens split by
+/// [`PhonemeMap::make_alignment()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TagPropagation {
+    /// Copies the tags to all split tokens.
+    Copy,
+
+    /// Keeps the tags only on the first split token.
+    First,
+}
+
 pub struct PhonemeMap {
     phoneme_map: HashMap2<Vec<char>, Vec<char>, Vec<(usize, usize)>>,
+
+    // Trained fragment scores used to align pairs that do not appear in the training data.
+    fragments: FragmentTable,
+    config: AlignerConfig,
 }
 
 impl PhonemeMap {
-    pub fn make_alignment(&self, sentence: &mut Sentence, tag_index: usize) {
+    /// Exports the phoneme map into a [`Vec`].
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut buf = PHONEME_MAP_MAGIC.to_vec();
+        buf.extend_from_slice(&PHONEME_MAP_VERSION.to_le_bytes());
+        utils::write_usize(&mut buf, self.config.max_surface_span);
+        utils::write_usize(&mut buf, self.config.max_reading_span);
+        buf.push(u8::from(self.config.allow_empty_reading));
+        utils::write_f64(&mut buf, self.config.deletion_penalty);
+        buf.push(u8::from(self.config.merge));
+        buf.push(u8::from(self.config.normalize_readings));
+        buf.push(u8::from(self.config.normalize_surfaces));
+        utils::write_usize(&mut buf, self.phoneme_map.len());
+        self.phoneme_map.for_each(|(surface, phoneme, alignments)| {
+            utils::write_chars(&mut buf, surface);
+            utils::write_chars(&mut buf, phoneme);
+            utils::write_usize(&mut buf, alignments.len());
+            // Boundaries are monotonically increasing, so differences are stored.
+            let mut surface_start_pos = 0;
+            let mut phoneme_start_pos = 0;
+            for &(surface_end_pos, phoneme_end_pos) in alignments {
+                utils::write_usize(&mut buf, surface_end_pos - surface_start_pos);
+                utils::write_usize(&mut buf, phoneme_end_pos - phoneme_start_pos);
+                surface_start_pos = surface_end_pos;
+                phoneme_start_pos = phoneme_end_pos;
+            }
+        });
+        utils::write_usize(&mut buf, self.fragments.len());
+        self.fragments.for_each(|surface, phoneme, score| {
+            utils::write_chars(&mut buf, &surface);
+            utils::write_chars(&mut buf, &phoneme);
+            utils::write_f64(&mut buf, score);
+        });
+        buf
+    }
+
+    /// Exports the phoneme map.
+    ///
+    /// # Errors
+    ///
+    /// When the writer generates an error, it will be returned as is.
+    #[cfg(feature = "std")]
+    pub fn write<W>(&self, mut wtr: W) -> Result<()>
+    where
+        W: Write,
+    {
+        wtr.write_all(&self.to_vec())?;
+        Ok(())
+    }
+
+    /// Creates a phoneme map from a slice and returns a tuple of the map and the remaining slice.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::InvalidModel`] will be returned if the data is truncated, broken, or written in
+    /// an unsupported format version.
+    pub fn read_slice(slice: &[u8]) -> Result<(Self, &[u8])> {
+        let mut rdr = SliceReader::new(slice);
+        if rdr.read_bytes(PHONEME_MAP_MAGIC.len()).ok() != Some(PHONEME_MAP_MAGIC) {
+            return Err(Error::invalid_model("not a phoneme map"));
+        }
+        let version = u32::from_le_bytes(rdr.read_bytes(4)?.try_into().unwrap());
+        if version != PHONEME_MAP_VERSION {
+            return Err(Error::invalid_model(format!(
+                "unsupported format version: {version} (expected {PHONEME_MAP_VERSION})"
+            )));
+        }
+        let mut config = AlignerConfig::new();
+        // Unbounded spans are stored as `usize::MAX` of the writer's platform.
+        config.max_surface_span = usize::try_from(rdr.read_varint()?).unwrap_or(usize::MAX);
+        config.max_reading_span = usize::try_from(rdr.read_varint()?).unwrap_or(usize::MAX);
+        config.allow_empty_reading = rdr.read_bool()?;
+        config.deletion_penalty = rdr.read_f64()?;
+        config.merge = rdr.read_bool()?;
+        config.normalize_readings = rdr.read_bool()?;
+        config.normalize_surfaces = rdr.read_bool()?;
+        if config.max_surface_span == 0
+            || config.max_reading_span == 0
+            || !config.deletion_penalty.is_finite()
+            || config.deletion_penalty < 0.0
+        {
+            return Err(Error::invalid_model("invalid configuration"));
+        }
+        let mut phoneme_map = HashMap2::new();
+        for _ in 0..rdr.read_usize()? {
+            let surface = rdr.read_chars()?;
+            let phoneme = rdr.read_chars()?;
+            let mut alignments = vec![];
+            let mut surface_end_pos = 0;
+            let mut phoneme_end_pos = 0;
+            for _ in 0..rdr.read_usize()? {
+                let surface_len = rdr.read_usize()?;
+                let phoneme_len = rdr.read_usize()?;
+                // Only a word of an empty surface has an empty surface fragment.
+                if surface_len == 0 && !surface.is_empty()
+                    || surface_len > surface.len() - surface_end_pos
+                    || phoneme_len > phoneme.len() - phoneme_end_pos
+                {
+                    return Err(Error::invalid_model("alignment out of range"));
+                }
+                surface_end_pos += surface_len;
+                phoneme_end_pos += phoneme_len;
+                alignments.push((surface_end_pos, phoneme_end_pos));
+            }
+            if (surface_end_pos, phoneme_end_pos) != (surface.len(), phoneme.len()) {
+                return Err(Error::invalid_model("alignment does not cover the word"));
+            }
+            phoneme_map.insert(surface, phoneme, alignments);
+        }
+        let mut fragments = FragmentTable::new();
+        for _ in 0..rdr.read_usize()? {
+            let surface = rdr.read_chars()?;
+            let phoneme = rdr.read_chars()?;
+            let score = rdr.read_f64()?;
+            fragments.insert(&surface, &phoneme, score)?;
+        }
+        Ok((
+            Self {
+                phoneme_map,
+                fragments,
+                config,
+            },
+            rdr.remaining(),
+        ))
+    }
+
+    /// Creates a phoneme map from a reader.
+    ///
+    /// # Errors
+    ///
+    /// When the reader generates an error, it will be returned as is.
+    /// [`Error::InvalidModel`] will be returned if the data is truncated, broken, or written in
+    /// an unsupported format version.
+    #[cfg(feature = "std")]
+    pub fn read<R>(mut rdr: R) -> Result<Self>
+    where
+        R: Read,
+    {
+        let mut buf = vec![];
+        rdr.read_to_end(&mut buf)?;
+        let (phoneme_map, rest) = Self::read_slice(&buf)?;
+        if !rest.is_empty() {
+            return Err(Error::invalid_model("trailing data after the phoneme map"));
+        }
+        Ok(phoneme_map)
+    }
+
+    /// Returns end positions of the aligned fragments of the given pair.
+    fn search_alignment(
+        &self,
+        surface: &[char],
+        phoneme: &[char],
+        best_nodes: &mut Array2d<(f64, usize, usize)>,
+    ) -> Result<Option<Vec<(usize, usize)>>> {
+        if let Some(alignments) = self.phoneme_map.get(surface, phoneme) {
+            return Ok(Some(alignments.clone()));
+        }
+        let pair_ids = self.fragments.lattice(&self.config, surface, phoneme)?;
+        Aligner::search_best_path(
+            &self.config,
+            surface.len(),
+            phoneme.len(),
+            &pair_ids,
+            self.fragments.scores(),
+            best_nodes,
+        )
+    }
+
+    /// Returns the score of the best path of the given segment.
+    fn segment_score(
+        &self,
+        surface: &[char],
+        phoneme: &[char],
+        best_nodes: &mut Array2d<(f64, usize, usize)>,
+    ) -> Result<f64> {
+        let pair_ids = self.fragments.lattice(&self.config, surface, phoneme)?;
+        Aligner::search_best_path(
+            &self.config,
+            surface.len(),
+            phoneme.len(),
+            &pair_ids,
+            self.fragments.scores(),
+            best_nodes,
+        )?;
+        Ok(best_nodes.get(0, 0).unwrap().0)
+    }
+
+    /// Aligns the given surface and reading, and returns pairs of the surface fragments and the
+    /// reading fragments.
+    ///
+    /// Pairs that do not appear in the training data are aligned in the same way as
+    /// [`PhonemeMap::make_alignment()`]. If no alignment is found, [`None`] is returned.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if the input is too long.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rualign::{Aligner, AlignerConfig};
+    ///
+    /// let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本", "ほん")];
+    /// let config = AlignerConfig::new().merge(false);
+    /// let mut aligner = Aligner::from_pairs(pairs, config)?;
+    /// for _ in 0..10 {
+    ///     aligner.update()?;
+    /// }
+    /// let phoneme_map = aligner.finalize()?;
+    /// let alignment = phoneme_map.align("日本", "にほん")?;
+    /// assert_eq!(Some(vec![("日", "に"), ("本", "ほん")]), alignment);
+    /// # Ok::<(), rualign::Error>(())
+    /// ```
+    pub fn align<'a>(
+        &self,
+        surface: &'a str,
+        reading: &'a str,
+    ) -> Result<Option<Vec<(&'a str, &'a str)>>> {
+        let surface = self.config.normalize_surface(surface);
+        let reading = self.config.normalize_reading(reading);
+        let mut best_nodes = Array2d::new(0, 0);
+        let Some(alignments) =
+            self.search_alignment(surface.chars(), reading.chars(), &mut best_nodes)?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(split_fragments(&surface, &reading, &alignments)))
+    }
+
+    /// Same as [`PhonemeMap::align()`], but also returns the score of the alignment and the
+    /// posterior probability of each segment within the word.
+    ///
+    /// Posterior probabilities are calculated by the forward-backward algorithm using the trained
+    /// fragment scores. A segment made by merging fragments is given the probability of the best
+    /// sequence of fragments within the segment, which is searched again using the scores and
+    /// may differ from the merged fragments. If the pair cannot be aligned under the scores, all
+    /// probabilities are 0.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if the input is too long.
+    pub fn align_with_posteriors<'a>(
+        &self,
+        surface: &'a str,
+        reading: &'a str,
+    ) -> Result<Option<Alignment<'a>>> {
+        let surface = self.config.normalize_surface(surface);
+        let reading = self.config.normalize_reading(reading);
+        let (surface_chars, reading_chars) = (surface.chars(), reading.chars());
+        let pair_ids = self
+            .fragments
+            .lattice(&self.config, surface_chars, reading_chars)?;
+        let scores = self.fragments.scores();
+        let mut best_nodes = Array2d::new(0, 0);
+        let ends = if let Some(ends) = self.phoneme_map.get(surface_chars, reading_chars) {
+            ends.clone()
+        } else {
+            let Some(ends) = Aligner::search_best_path(
+                &self.config,
+                surface_chars.len(),
+                reading_chars.len(),
+                &pair_ids,
+                scores,
+                &mut best_nodes,
+            )?
+            else {
+                return Ok(None);
+            };
+            ends
+        };
+        let mut alphas = Array2d::new(0, 0);
+        let mut betas = Array2d::new(0, 0);
+        Aligner::calculate_alphas(
+            &self.config,
+            surface_chars.len(),
+            reading_chars.len(),
+            &pair_ids,
+            scores,
+            &mut alphas,
+        )?;
+        Aligner::calculate_betas(
+            &self.config,
+            surface_chars.len(),
+            reading_chars.len(),
+            &pair_ids,
+            scores,
+            &mut betas,
+        )?;
+        // Stored alignments were searched on the lattices restricted in training, so they are not
+        // always on the chain of the best nodes. Each segment is scored by the best sub-path
+        // within it, which is the edge itself unless fragments are merged.
+        let mut path = vec![PathNode {
+            i: surface_chars.len(),
+            j: reading_chars.len(),
+            score: 0.0,
+            pair_id: NO_PAIR,
+        }];
+        for k in (0..ends.len()).rev() {
+            let (i, j) = k.checked_sub(1).map_or((0, 0), |k| ends[k]);
+            let (p, q) = ends[k];
+            let segment_score =
+                self.segment_score(&surface_chars[i..p], &reading_chars[j..q], &mut best_nodes)?;
+            path.push(PathNode {
+                i,
+                j,
+                score: path.last().unwrap().score + segment_score,
+                pair_id: NO_PAIR,
+            });
+        }
+        path.reverse();
+        Ok(Some(Alignment::new(
+            &surface, &reading, &path, &alphas, &betas,
+        )))
+    }
+
+    /// Returns the `n` best alignments of the given surface and reading in descending order of
+    /// scores.
+    ///
+    /// Unlike [`PhonemeMap::align()`], alignments are always searched using the trained fragment
+    /// scores, so fragments are not merged even if the pair appears in the training data.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if the input is too long.
+    pub fn align_n_best<'a>(
+        &self,
+        surface: &'a str,
+        reading: &'a str,
+        n: usize,
+    ) -> Result<Vec<Alignment<'a>>> {
+        Aligner::n_best_alignments(&self.config, &self.fragments, surface, reading, n)
+    }
+
+    /// Splits tokens of the given sentence into aligned fragments.
+    ///
+    /// Readings are taken from the tag layer `tag_index`, and the aligned reading fragments are
+    /// written into the tag layer `output_tag_index`. The two indices can be the same. The other
+    /// tag layers, including the original reading if the indices differ, are assigned to the
+    /// split tokens as specified by `propagation`.
+    ///
+    /// Pairs of a surface and a reading that do not appear in the training data are aligned by
+    /// the Viterbi search using the trained fragment scores. Such pairs are not merged by the
+    /// post-processing of [`Aligner::finalize()`]. If no alignment is found, the whole token is
+    /// kept as a single fragment.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`] will be returned if a token is too long.
+    pub fn make_alignment(
+        &self,
+        sentence: &mut Sentence,
+        tag_index: usize,
+        output_tag_index: usize,
+        propagation: TagPropagation,
+    ) -> Result<()> {
+        let n_tags = sentence.n_tags().max(output_tag_index + 1);
+        let mut best_nodes = Array2d::new(0, 0);
         let mut new_boundaries = vec![];
+        let mut new_tags = vec![];
         for token in sentence.iter_tokens() {
-            let phoneme = token
-                .tags()
+            let tags = token.tags();
+            let phoneme = tags
                 .get(tag_index)
                 .and_then(Option::as_ref)
                 .map_or("", |x| x.as_ref());
-            let surface: Vec<_> = token.surface().chars().collect();
+            let surface = self.config.normalize_surface(token.surface());
+            let phoneme_text = self.config.normalize_reading(phoneme);
+            let alignments = self
+                .search_alignment(surface.chars(), phoneme_text.chars(), &mut best_nodes)?
+                .unwrap_or_else(|| vec![(surface.chars().len(), phoneme_text.chars().len())]);
+            // Fragments are written in the original characters.
             let phoneme: Vec<_> = phoneme.chars().collect();
             let mut phoneme_start_pos = 0;
-            for &(surface_end_pos, phoneme_end_pos) in
-                self.phoneme_map.get(&surface, &phoneme).unwrap()
-            {
+            for (k, &(surface_end_pos, phoneme_end_pos)) in alignments.iter().enumerate() {
+                let surface_end_pos = surface.original_pos(surface_end_pos);
+                let phoneme_end_pos = phoneme_text.original_pos(phoneme_end_pos);
+                let start = new_tags.len();
+                new_tags.resize(start + n_tags, None);
+                if k == 0 || propagation == TagPropagation::Copy {
+                    new_tags[start..start + tags.len()].clone_from_slice(tags);
+                }
                 let phoneme: String = phoneme[phoneme_start_pos..phoneme_end_pos].iter().collect();
-                new_boundaries.push((token.start() + surface_end_pos - 1, phoneme));
+                new_tags[start + output_tag_index] = Some(phoneme.into());
+                new_boundaries.push(token.start() + surface_end_pos - 1);
                 phoneme_start_pos = phoneme_end_pos;
             }
         }
-        sentence.reset_tags(1);
-        for (pos, tag) in new_boundaries {
+        sentence.reset_tags(n_tags);
+        for (pos, tags) in new_boundaries
+            .into_iter()
+            .zip(new_tags.chunks_exact_mut(n_tags))
+        {
             if pos != sentence.boundaries().len() {
                 sentence.boundaries_mut()[pos] = CharacterBoundary::WordBoundary;
             }
-            sentence.tags_mut()[pos].replace(tag.into());
+            for (dst, src) in sentence.tags_mut()[pos * n_tags..(pos + 1) * n_tags]
+                .iter_mut()
+                .zip(tags)
+            {
+                *dst = src.take();
+            }
         }
+        Ok(())
     }
 }
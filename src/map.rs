@@ -27,36 +27,18 @@ where
             .insert(key2, value);
     }
 
-    pub fn get<Q1: ?Sized, Q2: ?Sized>(&self, key1: &Q1, key2: &Q2) -> Option<&V>
-    where
-        K1: Borrow<Q1>,
-        K2: Borrow<Q2>,
-        Q1: Hash + Eq,
-        Q2: Hash + Eq,
-    {
-        self.map.get(key1).and_then(|map| map.get(key2))
+    pub fn len(&self) -> usize {
+        self.map.values().map(HashMap::len).sum()
     }
 
-    pub fn contains_key<Q1: ?Sized, Q2: ?Sized>(&self, key1: &Q1, key2: &Q2) -> bool
+    pub fn get<Q1, Q2>(&self, key1: &Q1, key2: &Q2) -> Option<&V>
     where
         K1: Borrow<Q1>,
         K2: Borrow<Q2>,
-        Q1: Hash + Eq,
-        Q2: Hash + Eq,
+        Q1: Hash + Eq + ?Sized,
+        Q2: Hash + Eq + ?Sized,
     {
-        self.map
-            .get(key1)
-            .map_or(false, |map| map.contains_key(key2))
-    }
-
-    pub fn get_mut<Q1: ?Sized, Q2: ?Sized>(&mut self, key1: &Q1, key2: &Q2) -> Option<&mut V>
-    where
-        K1: Borrow<Q1>,
-        K2: Borrow<Q2>,
-        Q1: Hash + Eq,
-        Q2: Hash + Eq,
-    {
-        self.map.get_mut(key1).and_then(|map| map.get_mut(key2))
+        self.map.get(key1).and_then(|map| map.get(key2))
     }
 
     pub fn for_each<'a, F>(&'a self, mut f: F)
@@ -126,27 +108,20 @@ where
             .insert(key4);
     }
 
-    pub fn contains<Q1: ?Sized, Q2: ?Sized, Q3: ?Sized, Q4: ?Sized>(
-        &self,
-        key1: &Q1,
-        key2: &Q2,
-        key3: &Q3,
-        key4: &Q4,
-    ) -> bool
+    pub fn contains<Q1, Q2, Q3, Q4>(&self, key1: &Q1, key2: &Q2, key3: &Q3, key4: &Q4) -> bool
     where
         K1: Borrow<Q1>,
         K2: Borrow<Q2>,
         K3: Borrow<Q3>,
         K4: Borrow<Q4>,
-        Q1: Hash + Eq,
-        Q2: Hash + Eq,
-        Q3: Hash + Eq,
-        Q4: Hash + Eq,
+        Q1: Hash + Eq + ?Sized,
+        Q2: Hash + Eq + ?Sized,
+        Q3: Hash + Eq + ?Sized,
+        Q4: Hash + Eq + ?Sized,
     {
-        self.map.get(key1).map_or(false, |map| {
-            map.get(key2).map_or(false, |map| {
-                map.get(key3).map_or(false, |map| map.contains(key4))
-            })
+        self.map.get(key1).is_some_and(|map| {
+            map.get(key2)
+                .is_some_and(|map| map.get(key3).is_some_and(|map| map.contains(key4)))
         })
     }
 }
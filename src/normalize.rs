@@ -0,0 +1,106 @@
+use alloc::vec::Vec;
+
+use crate::kana;
+
+/// Converts a half-width katakana into the corresponding full-width character.
+fn to_full_width_katakana(c: char) -> Option<char> {
+    const TABLE: [char; 63] = [
+        '。', '「', '」', '、', '・', 'ヲ', 'ァ', 'ィ', 'ゥ', 'ェ', 'ォ', 'ャ', 'ュ', 'ョ', 'ッ',
+        'ー', 'ア', 'イ', 'ウ', 'エ', 'オ', 'カ', 'キ', 'ク', 'ケ', 'コ', 'サ', 'シ', 'ス', 'セ',
+        'ソ', 'タ', 'チ', 'ツ', 'テ', 'ト', 'ナ', 'ニ', 'ヌ', 'ネ', 'ノ', 'ハ', 'ヒ', 'フ', 'ヘ',
+        'ホ', 'マ', 'ミ', 'ム', 'メ', 'モ', 'ヤ', 'ユ', 'ヨ', 'ラ', 'リ', 'ル', 'レ', 'ロ', 'ワ',
+        'ン', '゛', '゜',
+    ];
+    let offset = u32::from(c).checked_sub(0xff61)?;
+    TABLE.get(usize::try_from(offset).ok()?).copied()
+}
+
+/// Combines a full-width katakana and a half-width (semi-)voiced sound mark.
+fn compose_voiced(c: char, mark: char) -> Option<char> {
+    let offset = match (c, mark) {
+        ('カ' | 'キ' | 'ク' | 'ケ' | 'コ' | 'サ' | 'シ' | 'ス' | 'セ' | 'ソ', 'ﾞ')
+        | ('タ' | 'チ' | 'ツ' | 'テ' | 'ト' | 'ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ', 'ﾞ') => {
+            1
+        }
+        ('ハ' | 'ヒ' | 'フ' | 'ヘ' | 'ホ', 'ﾟ') => 2,
+        ('ウ', 'ﾞ') => return Some('ヴ'),
+        _ => return None,
+    };
+    char::from_u32(u32::from(c) + offset)
+}
+
+/// Converts full-width ASCII characters and the ideographic space into ASCII characters. Other
+/// characters are returned as is.
+fn to_half_width_ascii(c: char) -> char {
+    match c {
+        '\u{ff01}'..='\u{ff5e}' => char::from_u32(u32::from(c) - 0xfee0).unwrap(),
+        '\u{3000}' => ' ',
+        _ => c,
+    }
+}
+
+/// A text converted into the canonical form, keeping the positions of the original characters.
+pub struct NormalizedText<'a> {
+    text: &'a str,
+    chars: Vec<char>,
+
+    // End position in the original characters of each normalized character.
+    ends: Vec<usize>,
+}
+
+impl<'a> NormalizedText<'a> {
+    /// Normalizes the given text.
+    ///
+    /// If `width` is true, full-width ASCII characters are converted into ASCII, and half-width
+    /// katakana into full-width, combining sound marks with the preceding letters. If `hiragana`
+    /// is true, katakana are converted into hiragana.
+    pub fn new(text: &'a str, width: bool, hiragana: bool) -> Self {
+        let mut chars: Vec<char> = vec![];
+        let mut ends = vec![];
+        for (pos, c) in text.chars().enumerate() {
+            let mut c = c;
+            if width {
+                if let Some(composed) = chars.last().and_then(|&prev| compose_voiced(prev, c)) {
+                    *chars.last_mut().unwrap() = composed;
+                    *ends.last_mut().unwrap() = pos + 1;
+                    continue;
+                }
+                c = to_full_width_katakana(c).unwrap_or_else(|| to_half_width_ascii(c));
+            }
+            chars.push(c);
+            ends.push(pos + 1);
+        }
+        if hiragana {
+            for c in &mut chars {
+                *c = kana::to_hiragana(*c);
+            }
+        }
+        Self { text, chars, ends }
+    }
+
+    /// Returns the original text.
+    pub const fn text(&self) -> &'a str {
+        self.text
+    }
+
+    /// Returns the normalized characters.
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Consumes the text and returns the normalized characters.
+    pub fn into_chars(self) -> Vec<char> {
+        self.chars
+    }
+
+    /// Converts a position in the normalized characters into the one in the original characters.
+    pub fn original_pos(&self, pos: usize) -> usize {
+        pos.checked_sub(1).map_or(0, |pos| self.ends[pos])
+    }
+
+    /// Converts a position in the original characters into the one in the normalized characters.
+    /// A position inside a combined character is moved to its start.
+    pub fn normalized_pos(&self, pos: usize) -> usize {
+        self.ends.partition_point(|&end| end <= pos)
+    }
+}
@@ -0,0 +1,197 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use hashbrown::HashMap;
+
+use crate::config::AlignerConfig;
+use crate::errors::Result;
+use crate::PhonemeMap;
+
+/// A reading predicted by [`Predictor`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction {
+    segments: Vec<(String, String)>,
+    reading: String,
+    score: f64,
+}
+
+impl Prediction {
+    /// Returns the predicted reading.
+    #[must_use]
+    pub fn reading(&self) -> &str {
+        &self.reading
+    }
+
+    /// Returns pairs of the surface fragments and the reading fragments.
+    #[must_use]
+    pub fn segments(&self) -> &[(String, String)] {
+        &self.segments
+    }
+
+    /// Returns the log score of the path.
+    #[must_use]
+    pub const fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+/// A node of the n-best search.
+#[derive(Clone)]
+struct Node {
+    score: f64,
+    prev_pos: usize,
+    prev_rank: usize,
+    candidate: usize,
+
+    // Reading of the path up to the node.
+    reading: Vec<char>,
+}
+
+/// Predictor of readings of surfaces without readings.
+///
+/// The predictor searches segmentations of the surface and readings of the fragments that maximize
+/// the trained fragment scores.
+///
+/// # Examples
+///
+/// ```
+/// use rualign::{Aligner, AlignerConfig, Predictor};
+///
+/// let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本", "ほん"), ("記入", "きにゅう")];
+/// let config = AlignerConfig::new().merge(false);
+/// let mut aligner = Aligner::from_pairs(pairs, config)?;
+/// for _ in 0..10 {
+///     aligner.update()?;
+/// }
+/// let predictor = Predictor::new(&aligner.finalize()?);
+/// let prediction = predictor.predict("本日")?.unwrap();
+/// assert_eq!("ほんに", prediction.reading());
+/// # Ok::<(), rualign::Error>(())
+/// ```
+pub struct Predictor {
+    // Pairs of a reading fragment and its score for each surface fragment.
+    candidates: HashMap<Vec<char>, Vec<(Vec<char>, f64)>>,
+    max_surface_span: usize,
+    config: AlignerConfig,
+}
+
+impl Predictor {
+    /// Creates a new predictor from the trained fragment scores of the given phoneme map.
+    #[must_use]
+    pub fn new(phoneme_map: &PhonemeMap) -> Self {
+        let mut candidates: HashMap<_, Vec<_>> = HashMap::new();
+        let mut max_surface_span = 0;
+        phoneme_map.fragments.for_each(|surface, reading, score| {
+            if surface.is_empty() || score == f64::NEG_INFINITY {
+                return;
+            }
+            max_surface_span = max_surface_span.max(surface.len());
+            candidates
+                .entry(surface)
+                .or_default()
+                .push((reading, score));
+        });
+        Self {
+            candidates,
+            max_surface_span,
+            config: phoneme_map.config.clone(),
+        }
+    }
+
+    /// Predicts the most probable reading of the given surface.
+    ///
+    /// If the surface contains a character that does not appear in the training data, [`None`]
+    /// is returned.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`](crate::Error::CastError) will be returned if the surface is too long.
+    pub fn predict(&self, surface: &str) -> Result<Option<Prediction>> {
+        Ok(self.predict_n_best(surface, 1)?.into_iter().next())
+    }
+
+    /// Predicts the `n` most probable readings of the given surface in descending order of
+    /// scores.
+    ///
+    /// Surface fragments are reported in the original characters, while readings are in the form
+    /// normalized as specified by the configuration.
+    ///
+    /// Different segmentations may yield the same reading. Such duplicates are removed, keeping
+    /// the best one, so fewer than `n` predictions are returned only if fewer distinct readings
+    /// exist.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::CastError`](crate::Error::CastError) will be returned if the surface is too long.
+    pub fn predict_n_best(&self, surface: &str, n: usize) -> Result<Vec<Prediction>> {
+        let original: Vec<_> = surface.chars().collect();
+        let normalized = self.config.normalize_surface(surface);
+        let surface = normalized.chars();
+        if n == 0 || surface.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // nodes[p] holds the n best paths of distinct readings that end at p in descending order
+        // of scores. A path whose reading is the same as a better one cannot lead to a new
+        // reading, so it is discarded.
+        let mut nodes: Vec<Vec<Node>> = vec![vec![]; surface.len() + 1];
+        nodes[0].push(Node {
+            score: 0.0,
+            prev_pos: 0,
+            prev_rank: 0,
+            candidate: 0,
+            reading: vec![],
+        });
+        for p in 1..surface.len() + 1 {
+            let mut new_nodes = vec![];
+            for i in p.saturating_sub(self.max_surface_span)..p {
+                let Some(candidates) = self.candidates.get(&surface[i..p]) else {
+                    continue;
+                };
+                for (candidate, (reading, score)) in candidates.iter().enumerate() {
+                    let distance = self.config.distance(0, 0, p - i, reading.len())?;
+                    for (prev_rank, prev_node) in nodes[i].iter().enumerate() {
+                        new_nodes.push(Node {
+                            score: score.mul_add(distance, prev_node.score),
+                            prev_pos: i,
+                            prev_rank,
+                            candidate,
+                            reading: [prev_node.reading.as_slice(), reading].concat(),
+                        });
+                    }
+                }
+            }
+            new_nodes.sort_by(|a, b| b.score.total_cmp(&a.score));
+            for node in new_nodes {
+                if nodes[p].len() == n {
+                    break;
+                }
+                if nodes[p].iter().all(|x| x.reading != node.reading) {
+                    nodes[p].push(node);
+                }
+            }
+        }
+
+        let mut predictions = vec![];
+        for last_node in &nodes[surface.len()] {
+            let mut segments = vec![];
+            let mut node = last_node;
+            let mut pos = surface.len();
+            while pos != 0 {
+                let reading = &self.candidates[&surface[node.prev_pos..pos]][node.candidate].0;
+                let original =
+                    &original[normalized.original_pos(node.prev_pos)..normalized.original_pos(pos)];
+                segments.push((original.iter().collect(), reading.iter().collect()));
+                pos = node.prev_pos;
+                node = &nodes[pos][node.prev_rank];
+            }
+            segments.reverse();
+            predictions.push(Prediction {
+                segments,
+                reading: last_node.reading.iter().collect(),
+                score: last_node.score,
+            });
+        }
+        Ok(predictions)
+    }
+}
@@ -0,0 +1,99 @@
+use alloc::vec::Vec;
+
+use crate::errors::{Error, Result};
+
+pub fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(u8::try_from(value & 0x7f).unwrap() | 0x80);
+        value >>= 7;
+    }
+    buf.push(u8::try_from(value).unwrap());
+}
+
+pub fn write_usize(buf: &mut Vec<u8>, value: usize) {
+    write_varint(buf, u64::try_from(value).unwrap());
+}
+
+pub fn write_f64(buf: &mut Vec<u8>, value: f64) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_chars(buf: &mut Vec<u8>, chars: &[char]) {
+    write_usize(buf, chars.len());
+    for &c in chars {
+        write_varint(buf, u64::from(u32::from(c)));
+    }
+}
+
+/// Reads values written by the `write_*` functions from a slice.
+pub struct SliceReader<'a> {
+    slice: &'a [u8],
+}
+
+impl<'a> SliceReader<'a> {
+    pub const fn new(slice: &'a [u8]) -> Self {
+        Self { slice }
+    }
+
+    pub const fn remaining(&self) -> &'a [u8] {
+        self.slice
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.slice.len() < len {
+            return Err(Error::invalid_model("unexpected end of data"));
+        }
+        let (bytes, rest) = self.slice.split_at(len);
+        self.slice = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_varint(&mut self) -> Result<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_bytes(1)?[0];
+            if shift >= 64 || (shift == 63 && byte > 1) {
+                return Err(Error::invalid_model("integer overflow"));
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    pub fn read_usize(&mut self) -> Result<usize> {
+        usize::try_from(self.read_varint()?).map_err(|_| Error::invalid_model("integer overflow"))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool> {
+        match self.read_bytes(1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::invalid_model("invalid boolean")),
+        }
+    }
+
+    pub fn read_f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn read_chars(&mut self) -> Result<Vec<char>> {
+        let len = self.read_usize()?;
+        // Each character occupies at least one byte.
+        if self.slice.len() < len {
+            return Err(Error::invalid_model("unexpected end of data"));
+        }
+        let mut chars = Vec::with_capacity(len);
+        for _ in 0..len {
+            let c = u32::try_from(self.read_varint()?)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| Error::invalid_model("invalid character"))?;
+            chars.push(c);
+        }
+        Ok(chars)
+    }
+}
@@ -0,0 +1,605 @@
+use rualign::{Aligner, AlignerConfig, Constraints, Error, Normalization, Prior, TagPropagation};
+use vaporetto::Sentence;
+
+fn align(training: &str, scores: Option<&str>, input: &str, propagation: TagPropagation) -> String {
+    let sentences = vec![Sentence::from_tokenized(training).unwrap()];
+    let config = AlignerConfig::new().merge(false);
+    let mut aligner = Aligner::new(&sentences, 1, config).unwrap();
+    if let Some(scores) = scores {
+        aligner.load_scores_tsv(scores).unwrap();
+    } else {
+        aligner.update().unwrap();
+    }
+    let phoneme_map = aligner.finalize().unwrap();
+    let mut sentence = Sentence::from_tokenized(input).unwrap();
+    phoneme_map
+        .make_alignment(&mut sentence, 1, 1, propagation)
+        .unwrap();
+    let mut buf = String::new();
+    sentence.write_tokenized_text(&mut buf);
+    buf
+}
+
+#[test]
+fn test_empty_reading() {
+    assert_eq!(
+        "猫/名詞/",
+        align("猫/名詞", None, "猫/名詞", TagPropagation::Copy),
+    );
+}
+
+#[test]
+fn test_empty_reading_with_other_words() {
+    assert_eq!(
+        "犬/名詞/いぬ 猫/名詞/",
+        align(
+            "犬/名詞/いぬ 猫/名詞",
+            None,
+            "犬/名詞/いぬ 猫/名詞",
+            TagPropagation::Copy,
+        ),
+    );
+}
+
+#[test]
+fn test_trailing_empty_reading_fragment() {
+    let scores = "日\tに\t-0.1\n本\t\t-0.1\n日本\tに\t-10\n日\t\t-10\n本\tに\t-10\n";
+    assert_eq!(
+        "日/名詞/に 本/名詞/",
+        align(
+            "日本/名詞/に",
+            Some(scores),
+            "日本/名詞/に",
+            TagPropagation::Copy,
+        ),
+    );
+}
+
+#[test]
+fn test_tags_on_first_fragment() {
+    let scores = "日\tに\t-0.1\n本\tほん\t-0.1\n日本\tにほん\t-10\n";
+    assert_eq!(
+        "日/名詞/に 本//ほん",
+        align(
+            "日本/名詞/にほん",
+            Some(scores),
+            "日本/名詞/にほん",
+            TagPropagation::First,
+        ),
+    );
+}
+
+#[test]
+fn test_output_tag_index() {
+    let sentences = vec![Sentence::from_tokenized("日本/名詞/にほん").unwrap()];
+    let config = AlignerConfig::new().merge(false);
+    let mut aligner = Aligner::new(&sentences, 1, config).unwrap();
+    aligner
+        .load_scores_tsv("日\tに\t-0.1\n本\tほん\t-0.1\n日本\tにほん\t-10\n")
+        .unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    let mut sentence = Sentence::from_tokenized("日本/名詞/にほん").unwrap();
+    phoneme_map
+        .make_alignment(&mut sentence, 1, 2, TagPropagation::Copy)
+        .unwrap();
+    let mut buf = String::new();
+    sentence.write_tokenized_text(&mut buf);
+    assert_eq!("日/名詞/にほん/に 本/名詞/にほん/ほん", buf);
+}
+
+#[test]
+fn test_align_unknown_characters() {
+    let sentences = vec![Sentence::from_tokenized("日本/名詞/にほん").unwrap()];
+    let mut aligner = Aligner::new(&sentences, 1, AlignerConfig::new()).unwrap();
+    aligner.update().unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    assert_eq!(None, phoneme_map.align("猫", "ねこ").unwrap());
+}
+
+#[test]
+fn test_align_n_best() {
+    let mut aligner = Aligner::from_pairs([("今日", "きょう")], AlignerConfig::new()).unwrap();
+    aligner
+        .load_scores_tsv("今日\tきょう\t-0.1\n今\tきょ\t-0.5\n日\tう\t-0.5\n")
+        .unwrap();
+    let alignments = aligner.align_n_best("今日", "きょう", 2).unwrap();
+    assert_eq!(2, alignments.len());
+    assert_eq!(&[("今日", "きょう")], alignments[0].segments());
+    assert_eq!(&[("今", "きょ"), ("日", "う")], alignments[1].segments());
+    assert!(alignments[0].score() > alignments[1].score());
+
+    let phoneme_map = aligner.finalize().unwrap();
+    assert_eq!(
+        alignments,
+        phoneme_map.align_n_best("今日", "きょう", 2).unwrap()
+    );
+}
+
+#[test]
+fn test_align_with_posteriors() {
+    let mut aligner = Aligner::from_pairs([("今日", "きょう")], AlignerConfig::new()).unwrap();
+    aligner
+        .load_scores_tsv("今日\tきょう\t-0.1\n今\tきょ\t-0.5\n日\tう\t-0.5\n")
+        .unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    let alignment = phoneme_map
+        .align_with_posteriors("今日", "きょう")
+        .unwrap()
+        .unwrap();
+    assert_eq!(&[("今日", "きょう")], alignment.segments());
+    assert_eq!(1, alignment.posteriors().len());
+    assert!(alignment.posteriors()[0] > 0.5 && alignment.posteriors()[0] <= 1.0);
+
+    let alignments = phoneme_map.align_n_best("今日", "きょう", 2).unwrap();
+    assert_eq!(alignment.posteriors(), alignments[0].posteriors());
+    let posteriors = alignments[1].posteriors();
+    assert!((posteriors[0] - posteriors[1]).abs() < 1e-9);
+    assert!(posteriors[0] + alignment.posteriors()[0] <= 1.0 + 1e-9);
+}
+
+#[test]
+fn test_two_phase_training() {
+    let pairs = [
+        ("猫x", "ねこ"),
+        ("犬x", "いぬ"),
+        ("猫", "ねこ"),
+        ("犬", "いぬ"),
+        ("猫犬", "ねこいぬ"),
+        ("犬猫x", "いぬねこ"),
+    ];
+    for normalization in [Normalization::Joint, Normalization::Reading] {
+        let config = AlignerConfig::new()
+            .merge(false)
+            .two_phase_training(true)
+            .normalization(normalization);
+        let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+        for _ in 0..20 {
+            if aligner.update().unwrap().log_squared_error() < -20.0 {
+                break;
+            }
+        }
+        for _ in 0..20 {
+            if aligner.update_n_best(3).unwrap().log_squared_error() < -20.0 {
+                break;
+            }
+        }
+        let phoneme_map = aligner.finalize().unwrap();
+        assert_eq!(
+            Some(vec![("猫", "ねこ"), ("x", "")]),
+            phoneme_map.align("猫x", "ねこ").unwrap(),
+        );
+    }
+}
+
+#[test]
+fn test_training_stats() {
+    let pairs = [("日本", "にほん"), ("猫", "")];
+    let config = AlignerConfig::new().allow_empty_reading(false);
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    let stats = aligner.update().unwrap();
+    assert_eq!(1, stats.num_unreachable_words());
+    assert!(stats.num_active_pairs() > 0);
+    assert!(stats.log_likelihood() < 0.0);
+    assert!(stats.log_squared_error().is_finite());
+}
+
+#[test]
+fn test_pruning() {
+    let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本", "ほん")];
+    let config = AlignerConfig::new().merge(false);
+    let mut aligner = Aligner::from_pairs(pairs, config.clone()).unwrap();
+    let mut pruned_aligner = Aligner::from_pairs(pairs, config.pruning_threshold(1e-4)).unwrap();
+    for _ in 0..10 {
+        let stats = aligner.update().unwrap();
+        let pruned_stats = pruned_aligner.update().unwrap();
+        assert!(pruned_stats.num_active_pairs() <= stats.num_active_pairs());
+    }
+    assert!(
+        pruned_aligner.scores_to_tsv().lines().count() < aligner.scores_to_tsv().lines().count()
+    );
+    let phoneme_map = pruned_aligner.finalize().unwrap();
+    assert_eq!(
+        Some(vec![("日", "に"), ("本", "ほん")]),
+        phoneme_map.align("日本", "にほん").unwrap(),
+    );
+}
+
+#[test]
+fn test_prior() {
+    let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本気", "ほんき")];
+    let mut tsvs = vec![];
+    for prior in [
+        Prior::None,
+        Prior::Additive(0.1),
+        Prior::VariationalBayes(0.1),
+    ] {
+        let config = AlignerConfig::new().merge(false).prior(prior);
+        let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+        for _ in 0..10 {
+            aligner.update().unwrap();
+        }
+        tsvs.push(aligner.scores_to_tsv());
+        let phoneme_map = aligner.finalize().unwrap();
+        assert_eq!(
+            Some(vec![("日", "に"), ("本", "ほん")]),
+            phoneme_map.align("日本", "にほん").unwrap(),
+        );
+    }
+    assert_ne!(tsvs[0], tsvs[1]);
+    assert_ne!(tsvs[0], tsvs[2]);
+}
+
+#[test]
+fn test_prior_splits_long_fragments() {
+    // Without a prior, the word is kept as a single fragment because none of its parts appear
+    // in other words. The prior moves probability mass to shorter fragments, so the word is
+    // split, although either of the two-fragment splits can be chosen.
+    let pairs = [("話す", "はなす"), ("は", "は"), ("を", "を")];
+    let mut alignments = vec![];
+    for prior in [Prior::None, Prior::Additive(0.1)] {
+        let config = AlignerConfig::new().merge(false).prior(prior);
+        let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+        for _ in 0..10 {
+            aligner.update().unwrap();
+        }
+        let phoneme_map = aligner.finalize().unwrap();
+        alignments.push(phoneme_map.align("話す", "はなす").unwrap().unwrap());
+    }
+    assert_eq!(vec![("話す", "はなす")], alignments[0]);
+    assert_eq!(2, alignments[1].len());
+    assert_eq!(("話", "す"), (alignments[1][0].0, alignments[1][1].0));
+}
+
+#[test]
+fn test_variational_bayes_scores() {
+    // Each word has a single edge, so each pair has an expected count of 1, and the score is
+    // digamma(1 + alpha) - digamma(2 + 2 * alpha).
+    let pairs = [("a", "b"), ("c", "d")];
+    let expected = [
+        // digamma(1.5) - digamma(3) = (2 - γ - 2 ln 2) - (3/2 - γ)
+        (0.5, 0.5 - 2.0 * 2f64.ln()),
+        // digamma(11) - digamma(22) = -(1/11 + ... + 1/21)
+        (10.0, -(11..22).map(|k| 1.0 / f64::from(k)).sum::<f64>()),
+    ];
+    for (alpha, expected) in expected {
+        let config = AlignerConfig::new()
+            .allow_empty_reading(false)
+            .prior(Prior::VariationalBayes(alpha));
+        let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+        aligner.update().unwrap();
+        let scores = parse_scores(&aligner.scores_to_tsv());
+        assert_eq!(2, scores.len());
+        for (_, _, score) in scores {
+            assert!((score - expected).abs() < 1e-9, "{score} != {expected}");
+        }
+    }
+}
+
+#[test]
+fn test_normalization() {
+    let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本気", "ほんき")];
+    for (normalization, column) in [(Normalization::Surface, 0), (Normalization::Reading, 1)] {
+        let config = AlignerConfig::new().normalization(normalization);
+        let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+        aligner.update().unwrap();
+        let tsv = aligner.scores_to_tsv();
+        let mut sums = std::collections::HashMap::new();
+        for line in tsv.lines() {
+            let cols: Vec<_> = line.split('\t').collect();
+            let score: f64 = cols[2].parse().unwrap();
+            *sums.entry(cols[column]).or_insert(0.0) += score.exp();
+        }
+        for sum in sums.values() {
+            assert!((sum - 1.0).abs() < 1e-9);
+        }
+    }
+}
+
+#[test]
+fn test_normalization_without_counts() {
+    let pairs = [
+        ("食べる", "たべる"),
+        ("食う", "くう"),
+        ("パン", "ぱん"),
+        ("日本", "にほん"),
+    ];
+    let mut constraints = Constraints::new();
+    constraints.anchor("日本", "にほん", 1, 1);
+    for normalization in [Normalization::Surface, Normalization::Reading] {
+        let configs = [
+            AlignerConfig::new().kana_identity(true),
+            AlignerConfig::new(),
+            AlignerConfig::new().two_phase_training(true),
+        ];
+        for (k, config) in configs.into_iter().enumerate() {
+            let config = config.normalization(normalization);
+            let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+            if k == 1 {
+                aligner.apply_constraints(&constraints).unwrap();
+            }
+            for _ in 0..3 {
+                let stats = aligner.update().unwrap();
+                assert!(!stats.log_squared_error().is_nan());
+            }
+            let tsv = aligner.scores_to_tsv();
+            assert!(!tsv.contains("NaN"));
+            aligner.load_scores_tsv(&tsv).unwrap();
+            let phoneme_map = aligner.finalize().unwrap();
+            assert!(phoneme_map
+                .align_with_posteriors("食べ", "たべ")
+                .unwrap()
+                .is_some());
+        }
+    }
+}
+
+#[test]
+fn test_constraints() {
+    let pairs = [("日本", "にほん"), ("する", "する")];
+    let config = AlignerConfig::new().merge(false);
+
+    let mut constraints = Constraints::new();
+    constraints.allow("日", "に");
+    constraints.allow("日本", "");
+    constraints.anchor("する", "する", 1, 1);
+    let mut aligner = Aligner::from_pairs(pairs, config.clone()).unwrap();
+    aligner.apply_constraints(&constraints).unwrap();
+    aligner.update().unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    assert_eq!(
+        Some(vec![("日", "に"), ("本", "ほん")]),
+        phoneme_map.align("日本", "にほん").unwrap(),
+    );
+    assert_eq!(
+        Some(vec![("す", "す"), ("る", "る")]),
+        phoneme_map.align("する", "する").unwrap(),
+    );
+
+    let mut constraints = Constraints::new();
+    constraints.forbid("日本", "にほん");
+    constraints.forbid("日", "にほ");
+    constraints.forbid("日", "に");
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    aligner.apply_constraints(&constraints).unwrap();
+    aligner.update().unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    assert_eq!(
+        Some(vec![("日", "にほん"), ("本", "")]),
+        phoneme_map.align("日本", "にほん").unwrap(),
+    );
+}
+
+#[test]
+fn test_anchor_out_of_range() {
+    let pairs = [("猫", "ねこ")];
+    let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new()).unwrap();
+    for (surface_pos, reading_pos) in [(5, 5), (2, 1), (1, 3)] {
+        let mut constraints = Constraints::new();
+        constraints.anchor("猫", "ねこ", surface_pos, reading_pos);
+        assert!(matches!(
+            aligner.apply_constraints(&constraints),
+            Err(Error::InvalidModel(_)),
+        ));
+    }
+    let mut constraints = Constraints::new();
+    constraints.anchor("猫", "ねこ", 1, 2);
+    aligner.apply_constraints(&constraints).unwrap();
+}
+
+#[test]
+fn test_kana_identity() {
+    let pairs = [
+        ("食べる", "たべる"),
+        ("食う", "くう"),
+        ("パン", "ぱん"),
+        ("は", "わ"),
+    ];
+    let config = AlignerConfig::new().merge(false).kana_identity(true);
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    aligner.update().unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    assert_eq!(
+        Some(vec![("食", "た"), ("べ", "べ"), ("る", "る")]),
+        phoneme_map.align("食べる", "たべる").unwrap(),
+    );
+    assert_eq!(
+        Some(vec![("パ", "ぱ"), ("ン", "ん")]),
+        phoneme_map.align("パン", "ぱん").unwrap(),
+    );
+    assert_eq!(
+        Some(vec![("は", "わ")]),
+        phoneme_map.align("は", "わ").unwrap(),
+    );
+}
+
+#[test]
+fn test_text_normalization() {
+    let pairs = [
+        ("日本", "にほん"),
+        ("日記", "ニッキ"),
+        ("本", "ﾎﾝ"),
+        ("ＰＣ", "ピーシー"),
+    ];
+    let config = AlignerConfig::new()
+        .merge(false)
+        .normalize_readings(true)
+        .normalize_surfaces(true);
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    for _ in 0..10 {
+        aligner.update().unwrap();
+    }
+    assert!(aligner.scores_to_tsv().contains("本\tほん\t"));
+    let phoneme_map = aligner.finalize().unwrap();
+    assert_eq!(
+        Some(vec![("日", "ニ"), ("本", "ホン")]),
+        phoneme_map.align("日本", "ニホン").unwrap(),
+    );
+    assert_eq!(
+        Some(vec![("日", "ﾆ"), ("本", "ﾎﾝ")]),
+        phoneme_map.align("日本", "ﾆﾎﾝ").unwrap(),
+    );
+    assert_eq!(
+        Some(vec![("ＰＣ", "ﾋﾟｰｼｰ")]),
+        phoneme_map.align("ＰＣ", "ﾋﾟｰｼｰ").unwrap(),
+    );
+
+    let mut sentence = Sentence::from_tokenized("日記/名詞/にっき").unwrap();
+    phoneme_map
+        .make_alignment(&mut sentence, 1, 1, TagPropagation::Copy)
+        .unwrap();
+    let mut buf = String::new();
+    sentence.write_tokenized_text(&mut buf);
+    assert_eq!("日/名詞/に 記/名詞/っき", buf);
+}
+
+#[test]
+fn test_align_with_posteriors_constrained() {
+    let mut aligner =
+        Aligner::from_pairs([("今日", "きょう")], AlignerConfig::new().merge(false)).unwrap();
+    aligner
+        .load_scores_tsv("今日\tきょう\t-0.1\n今\tきょ\t-0.5\n日\tう\t-0.5\n")
+        .unwrap();
+    let mut constraints = Constraints::new();
+    constraints.anchor("今日", "きょう", 1, 2);
+    aligner.apply_constraints(&constraints).unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    let alignment = phoneme_map
+        .align_with_posteriors("今日", "きょう")
+        .unwrap()
+        .unwrap();
+    assert_eq!(&[("今", "きょ"), ("日", "う")], alignment.segments());
+    assert!((alignment.score() + 2.5).abs() < 1e-9);
+
+    let alignments = phoneme_map.align_n_best("今日", "きょう", 2).unwrap();
+    assert_eq!(alignments[1].segments(), alignment.segments());
+    assert!((alignments[1].score() - alignment.score()).abs() < 1e-9);
+    for (a, b) in alignment
+        .posteriors()
+        .iter()
+        .zip(alignments[1].posteriors())
+    {
+        assert!((a - b).abs() < 1e-9);
+    }
+    assert!(alignment.posteriors()[0] < 0.5);
+}
+
+#[test]
+fn test_kana_identity_small_ke() {
+    let pairs = [("三ヶ月だ", "さんかげつだ"), ("月", "つき"), ("三", "さん")];
+    let config = AlignerConfig::new().merge(false).kana_identity(true);
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    aligner.update().unwrap();
+    let phoneme_map = aligner.finalize().unwrap();
+    let alignment = phoneme_map
+        .align("三ヶ月だ", "さんかげつだ")
+        .unwrap()
+        .unwrap();
+    assert_eq!(Some(&("だ", "だ")), alignment.last());
+}
+
+#[test]
+fn test_kana_identity_with_anchors() {
+    let pairs = [("食べる", "たべる"), ("食う", "くう")];
+    let config = AlignerConfig::new().merge(false).kana_identity(true);
+    let mut constraints = Constraints::new();
+    // Cannot be satisfied together with the kana identity.
+    constraints.anchor("食べる", "たべる", 1, 2);
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    aligner.apply_constraints(&constraints).unwrap();
+    let stats = aligner.update().unwrap();
+    assert_eq!(0, stats.num_unreachable_words());
+    let phoneme_map = aligner.finalize().unwrap();
+    let alignment = phoneme_map.align("食べる", "たべる").unwrap().unwrap();
+    assert_eq!(("食", "たべ"), alignment[0]);
+}
+
+fn parse_scores(tsv: &str) -> Vec<(String, String, f64)> {
+    tsv.lines()
+        .map(|line| {
+            let cols: Vec<_> = line.split('\t').collect();
+            (cols[0].into(), cols[1].into(), cols[2].parse().unwrap())
+        })
+        .collect()
+}
+
+fn assert_scores_eq(expected: &str, actual: &str) {
+    let expected = parse_scores(expected);
+    let actual = parse_scores(actual);
+    assert_eq!(expected.len(), actual.len());
+    for (a, b) in expected.iter().zip(&actual) {
+        assert_eq!((&a.0, &a.1), (&b.0, &b.1));
+        assert!(
+            a.2 == b.2 || (a.2 - b.2).abs() < 1e-9,
+            "{}/{}: {} != {}",
+            a.0,
+            a.1,
+            a.2,
+            b.2,
+        );
+    }
+}
+
+#[test]
+fn test_repeated_pairs() {
+    // Every path has the same length without empty readings, so the initial scores do not
+    // depend on the size of the corpus.
+    let config = AlignerConfig::new().allow_empty_reading(false);
+    let sentences = vec![
+        Sentence::from_tokenized("日本/にほん 日記/にっき 日本/にほん").unwrap(),
+        Sentence::from_tokenized("本/ほん 日本/にほん").unwrap(),
+    ];
+    let mut repeated = Aligner::new(&sentences, 0, config.clone()).unwrap();
+    let pairs = [
+        ("日記", "にっき"),
+        ("日本", "にほん"),
+        ("本", "ほん"),
+        ("日本", "にほん"),
+        ("日本", "にほん"),
+    ];
+    let mut listed = Aligner::from_pairs(pairs, config.clone()).unwrap();
+    for _ in 0..5 {
+        let a = repeated.update().unwrap();
+        let b = listed.update().unwrap();
+        assert!((a.log_likelihood() - b.log_likelihood()).abs() < 1e-9);
+    }
+    assert_scores_eq(&repeated.scores_to_tsv(), &listed.scores_to_tsv());
+
+    // Repeating the whole corpus keeps the scores. The likelihood is doubled once the initial
+    // scores, which depend on the size of the corpus, are updated.
+    let mut once = Aligner::from_pairs(pairs, config.clone()).unwrap();
+    let mut twice = Aligner::from_pairs(pairs.iter().chain(&pairs).copied(), config).unwrap();
+    once.update().unwrap();
+    twice.update().unwrap();
+    for _ in 0..5 {
+        let a = once.update().unwrap();
+        let b = twice.update().unwrap();
+        assert!((2.0 * a.log_likelihood() - b.log_likelihood()).abs() < 1e-9);
+    }
+    assert_scores_eq(&once.scores_to_tsv(), &twice.scores_to_tsv());
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn test_parallel_e_step() {
+    let pairs = [
+        ("日本", "にほん"),
+        ("日記", "にっき"),
+        ("本", "ほん"),
+        ("記入", "きにゅう"),
+        ("入口", "いりぐち"),
+        ("口", "くち"),
+        ("本日", "ほんじつ"),
+    ];
+    // A single thread runs the E-step over the whole dataset in the same way as the serial
+    // version.
+    let config = AlignerConfig::new();
+    let mut serial = Aligner::from_pairs(pairs, config.clone().num_threads(1)).unwrap();
+    let mut parallel = Aligner::from_pairs(pairs, config.num_threads(4)).unwrap();
+    for _ in 0..5 {
+        let a = serial.update().unwrap();
+        let b = parallel.update().unwrap();
+        assert!((a.log_likelihood() - b.log_likelihood()).abs() < 1e-9);
+        assert_eq!(a.num_active_pairs(), b.num_active_pairs());
+    }
+    assert_scores_eq(&serial.scores_to_tsv(), &parallel.scores_to_tsv());
+}
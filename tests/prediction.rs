@@ -0,0 +1,71 @@
+use rualign::{Aligner, AlignerConfig, Predictor};
+
+fn predictor(scores: &str) -> Predictor {
+    let pairs = [("日本", "にほん")];
+    let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new().merge(false)).unwrap();
+    aligner.load_scores_tsv(scores).unwrap();
+    Predictor::new(&aligner.finalize().unwrap())
+}
+
+#[test]
+fn test_predict_n_best() {
+    let predictor = predictor("日\tに\t-0.1\n日\tひ\t-0.5\n本\tほん\t-0.1\n本\tもと\t-1\n");
+    let predictions = predictor.predict_n_best("本日", 3).unwrap();
+    let readings: Vec<_> = predictions.iter().map(|x| x.reading()).collect();
+    assert_eq!(vec!["ほんに", "ほんひ", "もとに"], readings);
+    assert_eq!(
+        &[
+            ("本".to_string(), "ほん".to_string()),
+            ("日".to_string(), "に".to_string()),
+        ],
+        predictions[0].segments(),
+    );
+    assert!(predictions[0].score() > predictions[1].score());
+}
+
+#[test]
+fn test_predict_duplicated_readings() {
+    let predictor = predictor("日\tに\t-0.1\n本\tほん\t-0.1\n日本\tにほん\t-0.5\n");
+    let predictions = predictor.predict_n_best("日本", 2).unwrap();
+    assert_eq!(2, predictions.len());
+    assert_eq!("にほん", predictions[0].reading());
+    assert_eq!(2, predictions[0].segments().len());
+    assert_ne!("にほん", predictions[1].reading());
+}
+
+#[test]
+fn test_predict_n_best_distinct_readings() {
+    // The second best path of each node has the same reading as the best one.
+    let predictor = predictor("日\tに\t-0.1\n本\tほん\t-0.1\n日本\tにほん\t-0.2\n本\tもと\t-1\n");
+    let predictions = predictor.predict_n_best("日本", 2).unwrap();
+    let readings: Vec<_> = predictions.iter().map(|x| x.reading()).collect();
+    assert_eq!(vec!["にほん", "にもと"], readings);
+}
+
+#[test]
+fn test_predict_unknown_character() {
+    let predictor = predictor("日\tに\t-0.1\n本\tほん\t-0.1\n");
+    assert_eq!(None, predictor.predict("猫").unwrap());
+}
+
+#[test]
+fn test_predict_normalized_surface() {
+    let pairs = [("ＰＣ", "ピーシー")];
+    let config = AlignerConfig::new()
+        .normalize_readings(true)
+        .normalize_surfaces(true);
+    let mut aligner = Aligner::from_pairs(pairs, config).unwrap();
+    aligner
+        .load_scores_tsv("Ｐ\tピー\t-0.1\nＣ\tシー\t-0.1\n")
+        .unwrap();
+    let predictor = Predictor::new(&aligner.finalize().unwrap());
+    let prediction = predictor.predict("PＣ").unwrap().unwrap();
+    assert_eq!("ぴーしー", prediction.reading());
+    assert_eq!(
+        &[
+            ("P".to_string(), "ぴー".to_string()),
+            ("Ｃ".to_string(), "しー".to_string()),
+        ],
+        prediction.segments(),
+    );
+}
@@ -0,0 +1,157 @@
+use rualign::{Aligner, AlignerConfig, Error, PhonemeMap};
+
+fn phoneme_map() -> PhonemeMap {
+    let pairs = [("日", "に")];
+    let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new().merge(false)).unwrap();
+    aligner.update().unwrap();
+    aligner.finalize().unwrap()
+}
+
+/// Returns the position of the alignment list of the word 日/に in the serialized map.
+fn alignment_pos(data: &[u8]) -> usize {
+    // The surface and the reading as varints, followed by the number of fragments.
+    let word = [1, 0xe5, 0xcb, 0x01, 1, 0xeb, 0x60, 1];
+    data.windows(word.len()).position(|w| w == word).unwrap() + word.len()
+}
+
+#[test]
+fn test_round_trip() {
+    let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本", "ほん")];
+    let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new().merge(false)).unwrap();
+    for _ in 0..10 {
+        aligner.update().unwrap();
+    }
+    let phoneme_map = aligner.finalize().unwrap();
+    let mut buf = vec![];
+    phoneme_map.write(&mut buf).unwrap();
+    let loaded = PhonemeMap::read(buf.as_slice()).unwrap();
+    for (surface, reading) in [("日本", "にほん"), ("日記", "にっき"), ("本日", "ほんに")]
+    {
+        assert_eq!(
+            phoneme_map.align(surface, reading).unwrap(),
+            loaded.align(surface, reading).unwrap(),
+        );
+    }
+
+    let mut data = phoneme_map.to_vec();
+    data.extend_from_slice(b"rest");
+    let (_, rest) = PhonemeMap::read_slice(&data).unwrap();
+    assert_eq!(b"rest", rest);
+    assert!(matches!(
+        PhonemeMap::read(data.as_slice()),
+        Err(Error::InvalidModel(_)),
+    ));
+}
+
+#[test]
+fn test_read_truncated() {
+    let data = phoneme_map().to_vec();
+    for len in 0..data.len() {
+        assert!(matches!(
+            PhonemeMap::read_slice(&data[..len]),
+            Err(Error::InvalidModel(_)),
+        ));
+    }
+}
+
+#[test]
+fn test_read_wrong_magic() {
+    let mut data = phoneme_map().to_vec();
+    data[0] ^= 0xff;
+    assert!(matches!(
+        PhonemeMap::read_slice(&data),
+        Err(Error::InvalidModel(_)),
+    ));
+}
+
+#[test]
+fn test_read_wrong_version() {
+    let mut data = phoneme_map().to_vec();
+    let pos = b"RualignPhonemeMap\n".len();
+    data[pos..pos + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(matches!(
+        PhonemeMap::read_slice(&data),
+        Err(Error::InvalidModel(_)),
+    ));
+}
+
+#[test]
+fn test_read_broken_alignment() {
+    let data = phoneme_map().to_vec();
+    let pos = alignment_pos(&data);
+    assert_eq!(&[1, 1], &data[pos..pos + 2]);
+
+    // An empty surface fragment
+    let mut broken = data.clone();
+    broken[pos] = 0;
+    assert!(matches!(
+        PhonemeMap::read_slice(&broken),
+        Err(Error::InvalidModel(_)),
+    ));
+
+    // Fragments that do not cover the reading
+    let mut broken = data;
+    broken[pos + 1] = 0;
+    assert!(matches!(
+        PhonemeMap::read_slice(&broken),
+        Err(Error::InvalidModel(_)),
+    ));
+}
+
+#[test]
+fn test_scores_tsv_round_trip() {
+    let pairs = [("日本", "にほん"), ("日記", "にっき"), ("本", "ほん")];
+    let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new()).unwrap();
+    for _ in 0..3 {
+        aligner.update().unwrap();
+    }
+    let tsv = aligner.scores_to_tsv();
+    assert!(tsv.contains("本\tほん\t"));
+
+    let mut loaded = Aligner::from_pairs(pairs, AlignerConfig::new()).unwrap();
+    loaded.load_scores_tsv(&tsv).unwrap();
+    assert_eq!(tsv, loaded.scores_to_tsv());
+
+    let mut buf = vec![];
+    aligner.write_scores(&mut buf).unwrap();
+    let mut loaded = Aligner::from_pairs(pairs, AlignerConfig::new()).unwrap();
+    loaded.read_scores(buf.as_slice()).unwrap();
+    assert_eq!(tsv, loaded.scores_to_tsv());
+}
+
+#[test]
+fn test_load_malformed_scores_tsv() {
+    let mut aligner = Aligner::from_pairs([("日本", "にほん")], AlignerConfig::new()).unwrap();
+    let tsv = aligner.scores_to_tsv();
+    for malformed in [
+        "日\tに\n",
+        "日\tに\t-0.1\textra\n",
+        "\tに\t-0.1\n",
+        "日\tに\tNaN\n",
+        "日\tに\tabc\n",
+        "日\tに\tinf\n",
+        "日\tに\t0.5\n",
+        "日\\x\tに\t-0.1\n",
+        "本\tほん\t-0.1\n日\tに\n",
+    ] {
+        assert!(matches!(
+            aligner.load_scores_tsv(malformed),
+            Err(Error::InvalidModel(_)),
+        ));
+        assert_eq!(tsv, aligner.scores_to_tsv());
+    }
+}
+
+#[test]
+fn test_scores_tsv_escape() {
+    let pairs = [("a\tb", "x\ny"), ("c\\", "z\r")];
+    let mut aligner = Aligner::from_pairs(pairs, AlignerConfig::new()).unwrap();
+    aligner.update().unwrap();
+    let tsv = aligner.scores_to_tsv();
+    assert!(tsv.contains("a\\tb\tx\\ny\t"));
+    assert!(tsv.contains("c\\\\\tz\\r\t"));
+
+    let mut loaded = Aligner::from_pairs(pairs, AlignerConfig::new()).unwrap();
+    loaded.load_scores_tsv(&tsv).unwrap();
+    assert_eq!(tsv, loaded.scores_to_tsv());
+}